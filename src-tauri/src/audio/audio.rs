@@ -1,77 +1,129 @@
 #![allow(non_snake_case)]
-use crate::{audio::func, types::SharedAsyncRwLock};
+use crate::{
+    audio::{
+        device_state::Activity,
+        func,
+        supervisor::{Supervisor, Worker},
+    },
+    error::AppError,
+    types::SharedAsyncRwLock,
+};
 use std::ops::Not;
 use tracing::{debug, error, warn};
 
+/// 音频输入输出线程, 对话结束 (状态机离开活动状态) 后退出
 pub struct Audio {
-    pub is_audio_stoped: SharedAsyncRwLock<bool>,
     pub(super) audioInThread: Option<std::thread::JoinHandle<()>>,
     pub(super) audioOutThread: Option<std::thread::JoinHandle<()>>,
+    activity: Option<Activity>,
+    /// 为假时不打开音频设备, 输入输出线程仅等待对话结束
+    devices: bool,
 }
 
 impl Audio {
     pub(super) fn new() -> Self {
         Audio {
-            is_audio_stoped: SharedAsyncRwLock::new(true.into()),
             audioInThread: None,
             audioOutThread: None,
+            activity: None,
+            devices: true,
         }
     }
 
-    pub(super) async fn is_audio_stoped(&self) -> bool {
-        *self.is_audio_stoped.read().await
+    #[cfg(test)]
+    pub(super) fn without_devices() -> Self {
+        Audio {
+            audioInThread: None,
+            audioOutThread: None,
+            activity: None,
+            devices: false,
+        }
     }
 
-    pub(super) async fn set_audio_stoped(&mut self, stoped: bool) {
-        self.is_audio_stoped.write().await.clone_from(&stoped);
+    fn is_running(&self) -> bool {
+        self.audioInThread.is_some() || self.audioOutThread.is_some()
     }
 
+    /// 启动输入输出线程, `microphone` 为假时仅启动输出
     pub(super) async fn start(
         audio: SharedAsyncRwLock<Self>,
         audio_cache: SharedAsyncRwLock<crate::audio::cache::AudioCache>,
+        supervisor: Supervisor,
+        activity: Activity,
+        microphone: bool,
     ) {
-        if audio.read().await.is_audio_stoped().await.not() {
+        if audio.read().await.is_running() {
             debug!("音频已启动，拒绝再次启动");
             return;
         }
-        audio.write().await.set_audio_stoped(false).await;
-
-        let audioStoped_ = audio.read().await.is_audio_stoped.clone();
+        audio.write().await.activity.replace(activity.clone());
+        let devices = audio.read().await.devices;
 
+        let activity_ = activity.clone();
         let audio_cache_ = audio_cache.clone();
 
-        let in_thread = std::thread::Builder::new()
-            .name("音频输入线程".into())
-            .spawn(move || {
-                tauri::async_runtime::block_on(func::input(audioStoped_, audio_cache_));
-                // func::input(audioStoped_);
-            })
-            .inspect_err(|e| error!("音频输入线程启动失败: {}", e))
-            .unwrap();
-
-        let audioStoped_ = audio.read().await.is_audio_stoped.clone();
+        // 仅播放时不打开麦克风
+        let in_thread = if microphone {
+            let supervisor_ = supervisor.clone();
+            std::thread::Builder::new()
+                .name("音频输入线程".into())
+                .spawn(move || {
+                    supervisor_.run(Worker::Input, || match devices {
+                        true => tauri::async_runtime::block_on(func::input(
+                            activity_,
+                            audio_cache_,
+                            supervisor_.clone(),
+                        )),
+                        false => func::idle(activity_),
+                    });
+                })
+                .inspect_err(|e| {
+                    supervisor.report(
+                        Worker::Input,
+                        AppError::Internal(format!("音频输入线程启动失败: {}", e)),
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        let supervisor_ = supervisor.clone();
         let out_thread = std::thread::Builder::new()
             .name("音频输出线程".into())
             .spawn(move || {
-                tauri::async_runtime::block_on(func::output(audioStoped_, audio_cache));
-                // func::output(audioStoped_);
+                supervisor_.run(Worker::Output, || match devices {
+                    true => tauri::async_runtime::block_on(func::output(
+                        activity,
+                        audio_cache,
+                        supervisor_.clone(),
+                    )),
+                    false => func::idle(activity),
+                });
+            })
+            .inspect_err(|e| {
+                supervisor.report(
+                    Worker::Output,
+                    AppError::Internal(format!("音频输出线程启动失败: {}", e)),
+                )
             })
-            .inspect_err(|e| error!("音频输出线程启动失败: {}", e))
-            .unwrap();
+            .ok();
 
-        audio.write().await.audioInThread.replace(in_thread);
-        audio.write().await.audioOutThread.replace(out_thread);
+        audio.write().await.audioInThread = in_thread;
+        audio.write().await.audioOutThread = out_thread;
     }
 
+    /// 等待输入输出线程退出, 需先结束对话
     pub(super) async fn close(&mut self) {
         debug!("音频停止中...");
-        if *self.is_audio_stoped.read().await {
+        if self.is_running().not() {
             warn!("音频已停止，无需再次停止");
             return;
         }
-        self.is_audio_stoped.write().await.clone_from(&true);
-
-        debug!("音频停机信号已发送...");
+        if self.activity.as_ref().is_some_and(Activity::is_active) {
+            warn!("对话仍在进行，音频线程将在对话结束后退出");
+            return;
+        }
 
         if let Some(thread) = self.audioInThread.take() {
             thread.join().unwrap_or_else(|_e| {
@@ -1,19 +1,54 @@
+use crate::audio::codec::{self, Codec};
+use crate::audio::device_state::Activity;
+use crate::audio::recorder::{Direction, Recorder};
+use crate::audio::supervisor::{Supervisor, Worker};
+use crate::audio::vad::{Vad, VadEvent};
+use crate::error::AppError;
 use crate::types::SharedAsyncMutex;
 use crate::types::SharedAsyncRwLock;
-use crate::utils::config::Config;
-use crate::utils::ws::WebsocketProtocol;
+use crate::utils::config::{Config, paths};
+use crate::utils::frame::client::AudioParams;
+use crate::utils::queue::{self, DropCounters, Dropped};
+use crate::utils::transport::{AudioPacket, SharedTransport};
+use futures_util::StreamExt;
 use rubato::FftFixedIn;
 use rubato::Resampler;
-use std::i16;
+use serde::Serialize;
+use std::collections::VecDeque;
 use std::ops::Mul;
 use std::ops::Not;
+use std::sync::Arc;
 use std::sync::Once;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 
 const BUFFER_N: usize = 10;
+/// 输入缓存上限 (毫秒), 编码跟不上采集时丢弃最旧的采样
+const INPUT_BUFFER_MS: usize = 2000;
+/// 等待下行音频的最长时间, 超时后检查对话状态
+const RECV_WAIT: Duration = Duration::from_millis(100);
+
+/// 将按声道分开的数据转换为 `channels` 声道的交错数据
+///
+/// 声道数不足时复制最后一个声道, 转为单声道时取各声道平均值
+fn remix(input: &[Vec<f32>], channels: usize) -> Vec<f32> {
+    let frames = input.iter().map(Vec::len).min().unwrap_or(0);
+    let mut output = Vec::with_capacity(frames * channels);
+    for i in 0..frames {
+        if channels == 1 {
+            output.push(input.iter().map(|e| e[i]).sum::<f32>() / input.len() as f32);
+            continue;
+        }
+        for channel in 0..channels {
+            output.push(input[channel.min(input.len() - 1)][i]);
+        }
+    }
+    output
+}
 
 #[allow(non_snake_case)]
 pub struct AudioCache {
@@ -21,160 +56,204 @@ pub struct AudioCache {
     inputRate: u32,
     /// 输出音频采样率
     outputRate: u32,
+    /// 下行音频参数, 对话开始时按服务器 `hello` 协商结果更新
+    downlink: AudioParams,
 
     /// 输入音频数据
     rawInPCMData: SharedAsyncRwLock<Vec<f32>>,
     /// 采样率转换后的音频数据
     resampledInData: SharedAsyncRwLock<Vec<f32>>,
-    /// Opus编码后的音频数据
+    /// 编码后的音频数据
     opusInData: SharedAsyncRwLock<Vec<Vec<u8>>>,
 
     /// 重采样输出音频数据
     rawOutPCMData: SharedAsyncRwLock<Vec<i16>>,
     /// Opus解码后的音频数据
     decodedOutData: SharedAsyncRwLock<Vec<i16>>,
-    /// 服务器接收的Opus编码音频数据
-    opusOutData: SharedAsyncRwLock<Vec<Vec<u8>>>,
-    opsuEncoder: SharedAsyncMutex<opus::Encoder>,
-    opsuDecoder: SharedAsyncMutex<opus::Decoder>,
+    /// 服务器接收的编码音频数据
+    opusOutData: SharedAsyncRwLock<VecDeque<AudioPacket>>,
+    /// 最近解码的下行音频的服务器时间戳
+    downlinkTimestamp: SharedAsyncRwLock<Option<u32>>,
+    /// 上行编码器, 对话开始时创建
+    encoder: SharedAsyncMutex<Option<Box<dyn Codec>>>,
+    /// 下行解码器, 对话开始时按协商的音频参数创建
+    decoder: SharedAsyncMutex<Option<Box<dyn Codec>>>,
     /// 发送音频数据的线程
     sendThread: Option<tauri::async_runtime::JoinHandle<()>>,
     recvThread: Option<tauri::async_runtime::JoinHandle<()>>,
-    /// 发送音频数据的线程停止标志
-    send_stopped: SharedAsyncRwLock<bool>,
+    /// 收发线程在对话结束后退出
+    activity: Option<Activity>,
 
-    isSessionActive: bool,
     // 会话开始时，进行数据接收缓存
     sessionInit: Once,
+
+    /// 会话录制, 未启用时为 None
+    recorder: SharedAsyncMutex<Option<Recorder>>,
+    /// 输入音频的语音活动检测
+    vad: SharedAsyncMutex<Vad>,
+    /// 故障上报, 对话开始时设置
+    supervisor: Option<Supervisor>,
+    /// 创建时的配置, 配置变更后重建实例生效
+    config: Arc<Config>,
+    /// 缓存满时丢弃的数据量
+    dropped: DropCounters,
 }
 
 impl AudioCache {
     pub(super) fn new() -> Self {
+        Self::with_config(Config::get_instance())
+    }
+
+    /// 编解码器在对话开始时创建, 配置无效时不影响应用启动
+    pub(super) fn with_config(config: Arc<Config>) -> Self {
         debug!("AudioCache 初始化");
 
-        // SharedAsyncRwLock::new(
+        let downlink = config.opus.audio_params();
+        let capacity = config.opus.frame_size() * config.opus.channels * BUFFER_N;
         Self {
-            inputRate: Config::get_instance().input_device.sample_rate,
-            outputRate: Config::get_instance().output_device.sample_rate,
-            rawInPCMData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
-            resampledInData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
-            opusInData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
-
-            rawOutPCMData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
-            decodedOutData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
+            inputRate: config.input_device.sample_rate,
+            outputRate: config.output_device.sample_rate,
+            rawInPCMData: SharedAsyncRwLock::new(Vec::with_capacity(capacity).into()),
+            resampledInData: SharedAsyncRwLock::new(Vec::with_capacity(capacity).into()),
+            opusInData: SharedAsyncRwLock::new(Vec::with_capacity(capacity).into()),
+
+            rawOutPCMData: SharedAsyncRwLock::new(Vec::with_capacity(capacity).into()),
+            decodedOutData: SharedAsyncRwLock::new(Vec::with_capacity(capacity).into()),
             opusOutData: SharedAsyncRwLock::new(
-                Vec::with_capacity(Config::get_instance().websocket.frame_size * BUFFER_N).into(),
-            ),
-            opsuEncoder: SharedAsyncMutex::new(
-                opus::Encoder::new(
-                    Config::get_instance().opus.sample_rate as u32,
-                    opus::Channels::Mono,
-                    opus::Application::Audio,
-                )
-                .unwrap()
-                .into(),
-            ),
-            opsuDecoder: SharedAsyncMutex::new(
-                opus::Decoder::new(
-                    Config::get_instance().opus.sample_rate as u32,
-                    opus::Channels::Mono,
-                )
-                .unwrap()
-                .into(),
+                VecDeque::with_capacity(queue::AUDIO_CAPACITY).into(),
             ),
+            downlinkTimestamp: SharedAsyncRwLock::new(None.into()),
+            encoder: SharedAsyncMutex::new(None.into()),
+            decoder: SharedAsyncMutex::new(None.into()),
+            downlink,
             sendThread: None,
             recvThread: None,
-            send_stopped: SharedAsyncRwLock::new(true.into()),
-            isSessionActive: false,
+            activity: None,
             sessionInit: Once::new(),
+            recorder: SharedAsyncMutex::new(None.into()),
+            vad: SharedAsyncMutex::new(Vad::default().into()),
+            supervisor: None,
+            config,
+            dropped: DropCounters::default(),
         }
-
-        // )
     }
 
+    /// 创建编解码器并启动收发线程, 编解码器创建失败时返回错误
     pub(super) async fn start(
         audio_cache: SharedAsyncRwLock<Self>,
-        ws: SharedAsyncRwLock<WebsocketProtocol>,
-    ) {
-        if audio_cache.read().await.send_stopped.read().await.not() {
+        ws: SharedTransport,
+        supervisor: Supervisor,
+        activity: Activity,
+    ) -> Result<(), AppError> {
+        if audio_cache.read().await.sendThread.is_some() {
             debug!("AudioCache 数据发送线程已启动，拒绝重复启动");
-            return;
+            return Ok(());
         }
         audio_cache
-            .read()
-            .await
-            .send_stopped
             .write()
             .await
-            .clone_from(&false);
+            .supervisor
+            .replace(supervisor.clone());
+        // 每次对话使用新的编解码器, 以应用最新的编码参数
+        {
+            let mut audio_cache = audio_cache.write().await;
+            let opus = &audio_cache.config.opus;
+            let encoder = codec::new(&opus.audio_params(), opus)?;
+            // 下行按服务器声明的音频参数解码
+            let downlink = ws
+                .read()
+                .await
+                .get_server_audio_params()
+                .await
+                .unwrap_or_else(|| opus.audio_params());
+            if downlink != audio_cache.downlink {
+                info!("下行音频参数: {:?}", downlink);
+            }
+            let decoder = codec::new(&downlink, opus)?;
+            *audio_cache.encoder.lock().await = Some(encoder);
+            *audio_cache.decoder.lock().await = Some(decoder);
+            audio_cache.downlink = downlink;
+        }
+        audio_cache.write().await.activity.replace(activity.clone());
 
         let shared_audio_cache = audio_cache.clone();
-        let stop_flag = audio_cache.read().await.send_stopped.clone();
+        let activity_ = activity.clone();
         let ws_ = ws.clone();
+        let supervisor_ = supervisor.clone();
+        // 每帧时长发送一次
+        let interval =
+            Duration::from_millis(audio_cache.read().await.config.opus.frame_duration as u64);
 
         audio_cache
             .write()
             .await
             .sendThread
-            .replace(tauri::async_runtime::spawn(async move {
+            .replace(supervisor.spawn(Worker::Encoder, async move {
                 debug!("AudioCache 数据发送线程初始化");
-                while !*stop_flag.read().await {
+                while activity_.is_active() {
                     let ws__ = ws_.clone();
-                    shared_audio_cache.read().await.send_audio(ws__).await;
-                    tokio::time::sleep(tokio::time::Duration::from_millis(60)).await;
+                    if let Err(e) = shared_audio_cache.read().await.send_audio(ws__).await {
+                        supervisor_.report(Worker::Encoder, e);
+                        break;
+                    }
+                    tokio::time::sleep(interval).await;
                 }
                 debug!("AudioCache 数据发送线程退出");
             }));
 
         let shared_audio_cache = audio_cache.clone();
-        let stop_flag = audio_cache.read().await.send_stopped.clone();
+        let mut audio = ws.read().await.audio().await.unwrap_or_else(|| {
+            warn!("未取得下行音频流");
+            futures_util::stream::pending().boxed()
+        });
         audio_cache
             .write()
             .await
             .recvThread
-            .replace(tauri::async_runtime::spawn(async move {
+            .replace(supervisor.spawn(Worker::Decoder, async move {
                 debug!("AudioCache 数据接收线程初始化");
-                while !*stop_flag.read().await {
-                    let ws_ = ws.clone();
-                    if let Some(data) = ws_.read().await.read_audio_data().await {
-                        shared_audio_cache
-                            .read()
-                            .await
-                            .write_output_data(data)
-                            .await;
-                    } else {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                while activity.is_active() {
+                    // 超时后重新检查对话状态
+                    match tokio::time::timeout(RECV_WAIT, audio.next()).await {
+                        Ok(Some(packet)) => {
+                            shared_audio_cache
+                                .read()
+                                .await
+                                .write_output_data(packet)
+                                .await
+                        }
+                        // 连接已关闭, 由 AudioState_ 结束对话
+                        Ok(None) => audio = futures_util::stream::pending().boxed(),
+                        Err(_) => {}
                     }
                 }
                 debug!("AudioCache 数据接收线程退出");
             }));
+        Ok(())
     }
 
+    /// 等待收发线程退出并清空缓存, 需先结束对话
     pub(super) async fn reset(&mut self) {
-        self.send_stopped.write().await.clone_from(&true);
+        if self.activity.as_ref().is_some_and(Activity::is_active) {
+            warn!("对话仍在进行，AudioCache 收发线程将在对话结束后退出");
+            return;
+        }
         if let Some(st) = self.sendThread.take() {
             st.await
                 .inspect_err(|e| error!("AudioCache 数据发送线程退出失败: {}", e))
-                .unwrap();
+                .ok();
         }
 
         if let Some(st) = self.recvThread.take() {
             st.await
                 .inspect_err(|e| error!("AudioCache 数据接收线程退出失败: {}", e))
-                .unwrap();
+                .ok();
         }
 
         self.clear().await;
+        self.vad.lock().await.reset();
+        self.supervisor.take();
+        self.record_stop().await;
     }
 }
 
@@ -190,85 +269,104 @@ impl Drop for AudioCache {
 // input
 impl AudioCache {
     pub(super) async fn write_input_data(&self, data: Vec<f32>) {
-        self.rawInPCMData.write().await.append(&mut data.clone());
+        let frames = data.len() / self.config.input_device.channels.max(1);
+        let duration_ms = (frames as u64 * 1000 / self.inputRate.max(1) as u64) as u32;
+        self.vad.lock().await.update(&data, duration_ms);
+
+        let channels = self.config.input_device.channels.max(1);
+        let limit = self.inputRate as usize * channels * INPUT_BUFFER_MS / 1000;
+        let mut raw = self.rawInPCMData.write().await;
+        raw.extend_from_slice(&data);
+        if raw.len() > limit {
+            // 按整帧丢弃, 保持声道对齐
+            let excess = (raw.len() - limit).div_ceil(channels) * channels;
+            raw.drain(..excess);
+            self.dropped
+                .input_samples
+                .fetch_add(excess as u64, Ordering::Relaxed);
+        }
     }
 
-    async fn resample_in(&self) {
+    pub(super) async fn take_vad_event(&self) -> Option<VadEvent> {
+        self.vad.lock().await.take_event()
+    }
+
+    async fn resample_in(&self) -> Result<(), AppError> {
         let len = self.rawInPCMData.read().await.len();
 
-        if len
-            > Config::get_instance().websocket.frame_size
-                * Config::get_instance().input_device.channels
-        {
+        if len > self.config.opus.frame_size() * self.config.input_device.channels {
             let mut rawdata = self.rawInPCMData.write().await;
 
             let mut resampler = FftFixedIn::<f32>::new(
                 self.inputRate as usize,
-                Config::get_instance().opus.sample_rate,
-                len / Config::get_instance().input_device.channels,
+                self.config.opus.sample_rate,
+                len / self.config.input_device.channels,
                 10,
-                Config::get_instance().input_device.channels,
+                self.config.input_device.channels,
             )
-            .unwrap();
+            .map_err(|e| AppError::Codec(format!("重采样器创建失败: {}", e)))?;
 
-            let chunks = rawdata.chunks_exact(Config::get_instance().input_device.channels);
+            let chunks = rawdata.chunks_exact(self.config.input_device.channels);
             let remain = chunks.remainder().to_vec();
 
-            let mut input = vec![Vec::new(); Config::get_instance().input_device.channels];
+            let mut input = vec![Vec::new(); self.config.input_device.channels];
             for chunk in chunks {
                 for (channel, &value) in chunk.iter().enumerate() {
                     input[channel].push(value);
                 }
             }
-            let resampled = resampler.process(&input, None).expect("重采样失败");
+            let resampled = resampler
+                .process(&input, None)
+                .map_err(|e| AppError::Codec(format!("重采样失败: {}", e)))?;
 
-            // 将多通道数据转换为单通道数据
-            let single_channel: Vec<f32> = resampled[0].clone();
+            // 转换为编码声道数的交错数据
             self.resampledInData
                 .write()
                 .await
-                .append(&mut single_channel.clone());
+                .append(&mut remix(&resampled, self.config.opus.channels));
 
             *rawdata = remain;
         }
+        Ok(())
     }
 
-    async fn encode(&self) {
+    async fn encode(&self) -> Result<(), AppError> {
         let len = self.resampledInData.read().await.len();
-        if len >= Config::get_instance().websocket.frame_size {
-            let mut encoder = self.opsuEncoder.lock().await;
+        let mut encoder = self.encoder.lock().await;
+        let encoder = encoder
+            .as_mut()
+            .ok_or(AppError::Codec("编码器未初始化".to_string()))?;
+        let frame = encoder.frame_samples();
+        if len >= frame {
             let mut resampled = self.resampledInData.write().await;
 
-            // let remain = resampled.split_off(n * Config::get_instance().websocket.frame_size);
-            let chunks = resampled.chunks_exact(Config::get_instance().websocket.frame_size);
+            let chunks = resampled.chunks_exact(frame);
             let remain = chunks.remainder().to_vec();
 
             for chunk in chunks {
-                let mut output = vec![0u8; Config::get_instance().websocket.frame_size * BUFFER_N];
                 let input = chunk
-                    .into_iter()
+                    .iter()
                     .map(|e| e.mul(i16::MAX as f32) as i16)
                     .collect::<Vec<_>>();
 
-                let encode_size = encoder.encode(&input, &mut output).unwrap();
-                self.opusInData
-                    .write()
-                    .await
-                    .push(output[0..encode_size].to_vec());
+                let packet = encoder.encode(&input)?;
+                self.opusInData.write().await.push(packet);
             }
 
             *resampled = remain;
+            Ok(())
         } else {
-            self.resample_in().await;
+            self.resample_in().await
         }
     }
 
-    async fn send_audio(&self, ws: SharedAsyncRwLock<WebsocketProtocol>) {
+    async fn send_audio(&self, ws: SharedTransport) -> Result<(), AppError> {
         let len = self.opusInData.read().await.len();
         if len > 0 {
             // debug!("发送音频数据: {}", len);
             let mut opusdata = self.opusInData.write().await;
             for e in opusdata.iter() {
+                self.record_audio(Direction::Up, e).await;
                 let rst = ws.read().await.send_audio(e.clone()).await;
                 if let Err(e) = rst {
                     info!("发送数据帧失败: {}", e);
@@ -276,16 +374,25 @@ impl AudioCache {
             }
 
             opusdata.clear();
+            Ok(())
         } else {
-            self.encode().await;
+            self.encode().await
         }
     }
 }
 
 // output
 impl AudioCache {
-    async fn write_output_data(&self, data: Vec<u8>) {
-        self.opusOutData.write().await.push(data);
+    async fn write_output_data(&self, packet: AudioPacket) {
+        self.record_audio(Direction::Down, &packet.payload).await;
+        let mut opus_data = self.opusOutData.write().await;
+        if opus_data.len() >= queue::AUDIO_CAPACITY {
+            opus_data.pop_front();
+            self.dropped
+                .downlink_packets
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        opus_data.push_back(packet);
     }
 
     pub async fn read(&self, size: usize) -> Option<Vec<i16>> {
@@ -296,10 +403,6 @@ impl AudioCache {
             debug!("会话开始，进行数据接收缓存");
         });
 
-        if self.isSessionActive.not() {
-            return None;
-        }
-
         let len = self.rawOutPCMData.read().await.len();
         if len > size {
             let mut output = self.rawOutPCMData.write().await;
@@ -308,57 +411,76 @@ impl AudioCache {
             *output = remain;
             Some(ret)
         } else {
-            self.resample_out().await;
+            if let Err(e) = self.resample_out().await {
+                match self.supervisor.as_ref() {
+                    Some(supervisor) => supervisor.report(Worker::Decoder, e),
+                    None => error!("下行音频处理失败: {}", e),
+                }
+            }
             None
         }
     }
-    async fn resample_out(&self) {
+    async fn resample_out(&self) -> Result<(), AppError> {
         let len = self.decodedOutData.read().await.len();
-        if len > Config::get_instance().websocket.frame_size {
+        let channels = self.downlink.channels.max(1) as usize;
+        if len > self.downlink.frame_size() * channels {
             let mut decoded = self.decodedOutData.write().await;
 
             let mut resampler = FftFixedIn::<f32>::new(
-                Config::get_instance().opus.sample_rate,
+                self.downlink.sample_rate as usize,
                 self.outputRate as usize,
-                len,
+                len / channels,
                 10,
-                1,
+                channels,
             )
-            .unwrap();
+            .map_err(|e| AppError::Codec(format!("重采样器创建失败: {}", e)))?;
 
-            let input = vec![decoded.iter().map(|e| *e as f32).collect::<Vec<_>>()];
+            let mut input = vec![Vec::with_capacity(len / channels); channels];
+            for chunk in decoded.chunks_exact(channels) {
+                for (channel, &value) in chunk.iter().enumerate() {
+                    input[channel].push(value as f32);
+                }
+            }
 
-            let resampled = resampler.process(&input, None).expect("重采样失败");
+            let resampled = resampler
+                .process(&input, None)
+                .map_err(|e| AppError::Codec(format!("重采样失败: {}", e)))?;
 
-            let mut stereo_frame = Vec::new();
-            for sample in resampled[0].iter() {
-                stereo_frame.push(*sample as i16);
-                stereo_frame.push(*sample as i16);
-            }
+            // 转换为输出设备声道数的交错数据
+            let output = remix(&resampled, self.config.output_device.channels.max(1));
 
             decoded.clear();
-            self.rawOutPCMData.write().await.extend(stereo_frame);
-            // 将多通道数据转换为单通道数据
+            self.rawOutPCMData
+                .write()
+                .await
+                .extend(output.into_iter().map(|e| e as i16));
+            Ok(())
         } else {
-            self.decode().await;
+            self.decode().await
         }
     }
 
-    async fn decode(&self) {
+    async fn decode(&self) -> Result<(), AppError> {
         let len = self.opusOutData.read().await.len();
         if len > 0 {
             let mut opus_data = self.opusOutData.write().await;
 
-            let decoder = &mut self.opsuDecoder.lock().await;
+            let mut decoder = self.decoder.lock().await;
+            let decoder = decoder
+                .as_mut()
+                .ok_or(AppError::Codec("解码器未初始化".to_string()))?;
 
             let mut output = Vec::with_capacity(len);
 
-            opus_data.iter().for_each(|e| {
-                let mut temp = vec![0i16; Config::get_instance().websocket.frame_size * 10];
-                let size = decoder.decode(&e, &mut temp, false).unwrap();
-                output.push(temp[..size].to_vec());
+            // 出错时丢弃剩余数据包, 避免反复解码同一数据
+            let rst = opus_data.iter().try_for_each(|e| {
+                output.push(decoder.decode(&e.payload)?);
+                Ok::<_, AppError>(())
             });
 
+            if let Some(timestamp) = opus_data.iter().rev().find_map(|e| e.timestamp) {
+                self.downlinkTimestamp.write().await.replace(timestamp);
+            }
             opus_data.clear();
 
             self.decodedOutData.write().await.append(
@@ -369,6 +491,9 @@ impl AudioCache {
                     .collect::<Vec<_>>()
                     .as_mut(),
             );
+            rst
+        } else {
+            Ok(())
         }
     }
 }
@@ -379,10 +504,6 @@ impl AudioCache {
     // 目前输入音频数据不需要控制
 
     pub(super) async fn session_stop(&mut self) {
-        if self.isSessionActive.not() {
-            return;
-        }
-        self.isSessionActive = false;
         // 重置会话状态
         self.rawOutPCMData.write().await.clear();
         self.decodedOutData.write().await.clear();
@@ -392,10 +513,58 @@ impl AudioCache {
     }
     pub(super) fn session_start(&mut self) {
         self.sessionInit = Once::new();
-        self.isSessionActive = true;
         debug!("会话开始");
     }
 
+    /// 缓存满时丢弃的数据量
+    pub fn dropped(&self) -> Dropped {
+        self.dropped.snapshot()
+    }
+
+    /// 最近解码的下行音频的服务器时间戳, 协议不携带时为空
+    pub async fn downlink_timestamp(&self) -> Option<u32> {
+        *self.downlinkTimestamp.read().await
+    }
+
+    /// 下行尚未播放的数据量
+    pub(super) async fn output_pending(&self) -> usize {
+        self.opusOutData.read().await.len()
+            * self.downlink.frame_size()
+            * self.downlink.channels.max(1) as usize
+            + self.decodedOutData.read().await.len()
+            + self.rawOutPCMData.read().await.len()
+    }
+
+    /// 等待下行音频播放完毕
+    ///
+    /// 缓存中不足一帧的数据无法继续播放, 因此缓存量不再变化时也视为完成
+    pub(super) async fn drain(audio_cache: SharedAsyncRwLock<Self>, timeout: Duration) {
+        let mut last = usize::MAX;
+        let mut stalled = 0;
+        let drained = tokio::time::timeout(timeout, async {
+            loop {
+                let pending = audio_cache.read().await.output_pending().await;
+                if pending == 0 {
+                    break;
+                }
+                if pending == last {
+                    stalled += 1;
+                    if stalled >= 4 {
+                        break;
+                    }
+                } else {
+                    stalled = 0;
+                }
+                last = pending;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            warn!("等待下行音频播放超时，剩余数据将被丢弃");
+        }
+    }
+
     async fn clear(&self) {
         self.rawInPCMData.write().await.clear();
         self.resampledInData.write().await.clear();
@@ -405,3 +574,72 @@ impl AudioCache {
         // self.opusOutData.write().await.clear();
     }
 }
+
+// recorder
+impl AudioCache {
+    /// 需在 [`AudioCache::start`] 之后调用, 以使用协商后的下行音频参数
+    pub(super) async fn record_start(&self, session_id: &str) {
+        let cfg = &self.config.recorder;
+        if cfg.enabled.not() {
+            return;
+        }
+        if self.config.opus.format != codec::OPUS || self.downlink.format != codec::OPUS {
+            warn!("会话录制仅支持 Opus 格式, 跳过录制");
+            return;
+        }
+        let mut recorder = self.recorder.lock().await;
+        if recorder.is_some() {
+            return;
+        }
+        match Recorder::new(
+            &paths::resolve(&paths::data_dir(), &cfg.dir),
+            session_id,
+            &self.config.opus.audio_params(),
+            &self.downlink,
+        ) {
+            Ok(r) => {
+                info!("会话录制开始: {}", r.path().display());
+                recorder.replace(r);
+            }
+            Err(e) => error!("会话录制启动失败: {}", e),
+        }
+    }
+
+    pub(super) async fn record_frame(&self, direction: Direction, frame: &impl Serialize) {
+        if let Some(recorder) = self.recorder.lock().await.as_mut() {
+            let frame = serde_json::to_value(frame).unwrap_or_default();
+            if let Err(e) = recorder.write_frame(direction, frame) {
+                warn!("控制帧录制失败: {}", e);
+            }
+        }
+    }
+
+    async fn record_audio(&self, direction: Direction, packet: &[u8]) {
+        if let Some(recorder) = self.recorder.lock().await.as_mut()
+            && let Err(e) = recorder.write_audio(direction, packet)
+        {
+            warn!("音频录制失败: {}", e);
+        }
+    }
+
+    async fn record_stop(&self) {
+        if let Some(recorder) = self.recorder.lock().await.take() {
+            let path = recorder.path().to_path_buf();
+            match recorder.finish() {
+                Ok(_) => info!("会话录制完成: {}", path.display()),
+                Err(e) => error!("会话录制保存失败: {}", e),
+            }
+        }
+    }
+}
+
+#[test]
+fn f() {
+    let mono = vec![vec![0.1, 0.2]];
+    assert_eq!(remix(&mono, 1), [0.1, 0.2]);
+    assert_eq!(remix(&mono, 2), [0.1, 0.1, 0.2, 0.2]);
+
+    let stereo = vec![vec![0.5, 0.25], vec![0.25, 0.0]];
+    assert_eq!(remix(&stereo, 2), [0.5, 0.25, 0.25, 0.0]);
+    assert_eq!(remix(&stereo, 1), [0.375, 0.125]);
+}
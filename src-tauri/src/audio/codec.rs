@@ -0,0 +1,317 @@
+//! 音频编解码, 按 `hello` 中 `audio_params.format` 选择实现
+
+use crate::error::AppError;
+use crate::utils::config::OpusCfg;
+use crate::utils::frame::client::AudioParams;
+use audiopus_sys as ffi;
+use std::ffi::c_int;
+use std::ops::Not;
+use std::ptr::NonNull;
+
+pub const OPUS: &str = "opus";
+/// 16 bit 小端交错 PCM, 用于局域网部署与调试
+pub const PCM16: &str = "pcm16";
+pub const FORMATS: [&str; 2] = [OPUS, PCM16];
+
+/// Opus 单包最大字节数
+const MAX_PACKET_SIZE: usize = 4000;
+
+// opus_defines.h
+const OPUS_OK: c_int = 0;
+const OPUS_AUTO: c_int = -1000;
+const OPUS_APPLICATION_VOIP: c_int = 2048;
+const OPUS_SET_BITRATE_REQUEST: c_int = 4002;
+const OPUS_SET_VBR_REQUEST: c_int = 4006;
+const OPUS_SET_COMPLEXITY_REQUEST: c_int = 4010;
+const OPUS_SET_INBAND_FEC_REQUEST: c_int = 4012;
+const OPUS_SET_PACKET_LOSS_PERC_REQUEST: c_int = 4014;
+const OPUS_SET_DTX_REQUEST: c_int = 4016;
+
+pub trait Codec: Send {
+    fn params(&self) -> &AudioParams;
+
+    /// 每帧交错采样数
+    fn frame_samples(&self) -> usize {
+        self.params().frame_size() * self.params().channels as usize
+    }
+
+    /// 编码一帧交错 PCM 数据
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, AppError>;
+
+    /// 解码一个数据包, 返回交错 PCM 数据
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, AppError>;
+}
+
+/// 按音频参数创建编解码器, Opus 编码参数取自 `cfg`
+pub fn new(params: &AudioParams, cfg: &OpusCfg) -> Result<Box<dyn Codec>, AppError> {
+    if matches!(params.channels, 1 | 2).not() {
+        return Err(AppError::Codec(format!(
+            "不支持的声道数: {}",
+            params.channels
+        )));
+    }
+    match params.format.as_str() {
+        OPUS => Ok(Box::new(Opus::new(params, cfg)?)),
+        PCM16 => Ok(Box::new(Pcm16 {
+            params: params.clone(),
+        })),
+        format => Err(AppError::Codec(format!("不支持的音频格式: {}", format))),
+    }
+}
+
+/// Opus 语音编码器
+///
+/// opus 0.3.0 未提供复杂度与 DTX 的设置接口, 编码器直接使用 libopus 的 `opus_encoder_ctl`
+struct Encoder {
+    ptr: NonNull<ffi::OpusEncoder>,
+    channels: usize,
+}
+
+// libopus 编码器状态不依赖所在线程
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    fn new(sample_rate: u32, channels: usize) -> Result<Self, AppError> {
+        let mut code = OPUS_OK;
+        let ptr = unsafe {
+            ffi::opus_encoder_create(
+                sample_rate as i32,
+                channels as c_int,
+                OPUS_APPLICATION_VOIP,
+                &mut code,
+            )
+        };
+        match NonNull::new(ptr) {
+            Some(ptr) if code == OPUS_OK => Ok(Self { ptr, channels }),
+            _ => Err(AppError::Codec(format!("创建 Opus 编码器失败: {}", code))),
+        }
+    }
+
+    fn ctl(&mut self, request: c_int, value: c_int) -> Result<(), AppError> {
+        let code = unsafe { ffi::opus_encoder_ctl(self.ptr.as_ptr(), request, value) };
+        match code {
+            OPUS_OK => Ok(()),
+            _ => Err(AppError::Codec(format!(
+                "设置 Opus 编码参数 {} = {} 失败: {}",
+                request, value, code
+            ))),
+        }
+    }
+
+    /// 应用码率、VBR、复杂度、FEC、丢包率与 DTX
+    fn configure(&mut self, cfg: &OpusCfg) -> Result<(), AppError> {
+        self.ctl(OPUS_SET_BITRATE_REQUEST, cfg.bitrate.unwrap_or(OPUS_AUTO))?;
+        self.ctl(OPUS_SET_VBR_REQUEST, cfg.vbr as c_int)?;
+        self.ctl(OPUS_SET_COMPLEXITY_REQUEST, cfg.complexity)?;
+        self.ctl(OPUS_SET_INBAND_FEC_REQUEST, cfg.fec as c_int)?;
+        self.ctl(OPUS_SET_PACKET_LOSS_PERC_REQUEST, cfg.packet_loss)?;
+        self.ctl(OPUS_SET_DTX_REQUEST, cfg.dtx as c_int)
+    }
+
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, AppError> {
+        let mut output = vec![0u8; MAX_PACKET_SIZE];
+        let size = unsafe {
+            ffi::opus_encode(
+                self.ptr.as_ptr(),
+                pcm.as_ptr(),
+                (pcm.len() / self.channels) as c_int,
+                output.as_mut_ptr(),
+                output.len() as i32,
+            )
+        };
+        if size < 0 {
+            return Err(AppError::Codec(format!("Opus 编码失败: {}", size)));
+        }
+        output.truncate(size as usize);
+        Ok(output)
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_encoder_destroy(self.ptr.as_ptr()) }
+    }
+}
+
+pub struct Opus {
+    params: AudioParams,
+    encoder: Encoder,
+    decoder: opus::Decoder,
+}
+
+impl Opus {
+    fn new(params: &AudioParams, cfg: &OpusCfg) -> Result<Self, AppError> {
+        let channels = match params.channels {
+            1 => opus::Channels::Mono,
+            _ => opus::Channels::Stereo,
+        };
+        let mut encoder = Encoder::new(params.sample_rate, params.channels as usize)?;
+        encoder.configure(cfg)?;
+        Ok(Self {
+            params: params.clone(),
+            encoder,
+            decoder: opus::Decoder::new(params.sample_rate, channels)?,
+        })
+    }
+}
+
+impl Codec for Opus {
+    fn params(&self) -> &AudioParams {
+        &self.params
+    }
+
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, AppError> {
+        self.encoder.encode(pcm)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, AppError> {
+        let channels = self.params.channels as usize;
+        // 单包最长 120 ms
+        let mut output = vec![0i16; self.params.sample_rate as usize * 120 / 1000 * channels];
+        let size = self.decoder.decode(packet, &mut output, false)?;
+        output.truncate(size * channels);
+        Ok(output)
+    }
+}
+
+pub struct Pcm16 {
+    params: AudioParams,
+}
+
+impl Codec for Pcm16 {
+    fn params(&self) -> &AudioParams {
+        &self.params
+    }
+
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, AppError> {
+        Ok(pcm.iter().flat_map(|e| e.to_le_bytes()).collect())
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, AppError> {
+        let channels = self.params.channels as usize;
+        if packet.len().is_multiple_of(2 * channels).not() {
+            return Err(AppError::Codec(format!(
+                "PCM 数据长度 {} 与声道数 {} 不匹配",
+                packet.len(),
+                channels
+            )));
+        }
+        Ok(packet
+            .chunks_exact(2)
+            .map(|e| i16::from_le_bytes([e[0], e[1]]))
+            .collect())
+    }
+}
+
+#[test]
+fn f() {
+    let cfg = OpusCfg::default();
+    let params = AudioParams {
+        format: PCM16.to_string(),
+        sample_rate: 16000,
+        channels: 2,
+        frame_duration: 20,
+    };
+    let mut pcm = new(&params, &cfg).unwrap();
+    assert_eq!(pcm.frame_samples(), 640);
+    let frame = (0..640)
+        .map(|e| (e * 50 - 16000) as i16)
+        .collect::<Vec<_>>();
+    let packet = pcm.encode(&frame).unwrap();
+    assert_eq!(packet.len(), 1280);
+    assert_eq!(pcm.decode(&packet).unwrap(), frame);
+    assert!(pcm.decode(&packet[..3]).is_err());
+
+    let mut opus = new(&AudioParams::default(), &cfg).unwrap();
+    assert_eq!(opus.frame_samples(), 960);
+    let packet = opus.encode(&vec![0; 960]).unwrap();
+    assert!(packet.is_empty().not() && packet.len() < 960 * 2);
+    assert_eq!(opus.decode(&packet).unwrap().len(), 960);
+
+    for (format, channels) in [("aac", 1), (OPUS, 3)] {
+        let params = AudioParams {
+            format: format.to_string(),
+            channels,
+            ..Default::default()
+        };
+        assert!(new(&params, &cfg).is_err());
+    }
+
+    // 1 s 的 440 Hz 正弦波, 16 kHz
+    let fixture = (0..16000)
+        .map(|i| {
+            let t = i as f32 / 16000.0;
+            ((t * 440.0 * std::f32::consts::TAU).sin() * 0.5 * i16::MAX as f32) as i16
+        })
+        .collect::<Vec<_>>();
+    let sizes = |cfg: &OpusCfg, fixture: &[i16]| {
+        let mut codec = new(&cfg.audio_params(), cfg).unwrap();
+        let input = fixture
+            .iter()
+            .flat_map(|e| std::iter::repeat_n(*e, cfg.channels))
+            .collect::<Vec<_>>();
+        input
+            .chunks_exact(codec.frame_samples())
+            .map(|e| codec.encode(e).unwrap().len())
+            .collect::<Vec<_>>()
+    };
+
+    // (声道, 帧时长, 码率, 复杂度, FEC, 丢包率): CBR 下每包大小固定为 码率 * 帧时长 / 8
+    for (channels, frame_duration, bitrate, complexity, fec, packet_loss) in [
+        (1, 60, 16000, 10, false, 0),
+        (1, 60, 32000, 5, false, 0),
+        (1, 60, 16000, 10, true, 20),
+        (1, 20, 16000, 0, false, 0),
+        (2, 20, 32000, 10, false, 0),
+        (2, 40, 64000, 3, true, 10),
+    ] {
+        let cfg = OpusCfg {
+            channels,
+            frame_duration,
+            bitrate: Some(bitrate),
+            vbr: false,
+            complexity,
+            fec,
+            packet_loss,
+            ..Default::default()
+        };
+        let expected = bitrate as usize * frame_duration as usize / 1000 / 8;
+        let sizes = sizes(&cfg, &fixture);
+        assert_eq!(sizes.len(), 1000 / frame_duration as usize, "{:?}", cfg);
+        assert!(sizes.iter().all(|e| *e == expected), "{:?}", cfg);
+    }
+
+    // VBR 下平均包大小随码率变化
+    let average = |bitrate| {
+        let cfg = OpusCfg {
+            bitrate: Some(bitrate),
+            ..Default::default()
+        };
+        let sizes = sizes(&cfg, &fixture);
+        sizes.iter().sum::<usize>() / sizes.len()
+    };
+    let (low, high) = (average(12000), average(48000));
+    assert!(low > 0 && low < high, "{} {}", low, high);
+    assert!(high <= 48000 * 60 / 1000 / 8 * 2, "{}", high);
+
+    // DTX 开启后静音只发送极短的包
+    let silence = vec![0; 32000];
+    let total = |dtx| {
+        let cfg = OpusCfg {
+            frame_duration: 20,
+            dtx,
+            ..Default::default()
+        };
+        sizes(&cfg, &silence)
+    };
+    let (dtx, continuous) = (total(true), total(false));
+    assert!(dtx.iter().any(|e| *e <= 2), "{:?}", dtx);
+    assert!(dtx.iter().sum::<usize>() < continuous.iter().sum::<usize>());
+
+    // 超出范围的复杂度由 libopus 拒绝
+    let cfg = OpusCfg {
+        complexity: 11,
+        ..Default::default()
+    };
+    assert!(new(&cfg.audio_params(), &cfg).is_err());
+}
@@ -1,96 +1,198 @@
 use crate::{
-    audio::cache::AudioCache,
+    audio::{
+        cache::AudioCache,
+        device_state::{Activity, DeviceState, StateMachine},
+        recorder::Direction,
+        supervisor::{Supervisor, Worker},
+        vad::VadEvent,
+    },
     types::SharedAsyncRwLock,
-    utils::frame::{Frame, tts::TtsState},
+    utils::{
+        frame::{Frame, hook, listen::ListenState, tts::TtsState},
+        history::{History, HistoryEntry, Role},
+        transport::SharedTransport,
+    },
 };
+use futures_util::StreamExt;
 use std::ops::Not;
-use tauri::Emitter;
+use std::time::Duration;
+use tauri::Manager;
 use tracing::{debug, error, warn};
 
+/// 等待控制帧的最长时间, 超时后检查 VAD 事件与对话状态
+const FRAME_WAIT: Duration = Duration::from_millis(80);
+
+/// 处理服务器控制帧的工作线程, 对话结束后退出
 pub struct Controller {
     worker_thread: Option<tauri::async_runtime::JoinHandle<()>>,
-    is_stopped: SharedAsyncRwLock<bool>,
+    activity: Option<Activity>,
 }
 
 impl Controller {
     pub(super) fn new() -> Self {
         Self {
             worker_thread: None,
-            is_stopped: SharedAsyncRwLock::new(true.into()),
+            activity: None,
         }
     }
 
     pub(super) async fn start(
         controller: SharedAsyncRwLock<Self>,
         audio_cache: SharedAsyncRwLock<AudioCache>,
-        ws: SharedAsyncRwLock<crate::utils::ws::WebsocketProtocol>,
-        webview: Option<tauri::WebviewWindow>,
+        ws: SharedTransport,
+        state: SharedAsyncRwLock<StateMachine>,
+        supervisor: Supervisor,
     ) {
-        if controller.read().await.is_stopped.read().await.not() {
+        if controller.read().await.worker_thread.is_some() {
             warn!("已拒绝重复启动控制器工作线程");
             return;
         }
-        controller
-            .write()
-            .await
-            .is_stopped
-            .write()
-            .await
-            .clone_from(&false);
-
-        let is_stopped = controller.read().await.is_stopped.clone();
+        let activity = state.read().await.activity();
+        controller.write().await.activity.replace(activity.clone());
+        let mut frames = ws.read().await.frames().await.unwrap_or_else(|| {
+            warn!("未取得控制帧流");
+            futures_util::stream::pending().boxed()
+        });
 
         controller
             .write()
             .await
             .worker_thread
-            .replace(tauri::async_runtime::spawn(async move {
+            .replace(supervisor.spawn(Worker::Controller, async move {
+                // 本轮回复的 llm 帧携带的情绪, 附加到随后的语句上, 回复结束时清除
+                let mut emotion = None;
                 loop {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(80)).await;
-                    if *is_stopped.read().await {
+                    let frame = match tokio::time::timeout(FRAME_WAIT, frames.next()).await {
+                        Ok(None) => {
+                            // 连接已关闭, 由 AudioState_ 结束对话
+                            frames = futures_util::stream::pending().boxed();
+                            None
+                        }
+                        Ok(frame) => frame,
+                        Err(_) => None,
+                    };
+                    if activity.is_active().not() {
                         break;
                     }
-                    if let Some(frame) = ws.read().await.read_text_frame().await {
+                    // 用户说话结束, 等待服务器响应
+                    if let Some(VadEvent::SpeechEnd) =
+                        audio_cache.read().await.take_vad_event().await
+                        && state.read().await.state() == DeviceState::Listening
+                    {
+                        Self::transition(&state, DeviceState::Thinking).await;
+                    }
+                    if let Some(frame) = frame {
+                        audio_cache
+                            .read()
+                            .await
+                            .record_frame(Direction::Down, &frame)
+                            .await;
                         match frame {
-                            Frame::TtsFrame(frame) => {
-                                match frame.state {
-                                    TtsState::Start => {}
-                                    TtsState::Stop => {
-                                        // XXX 后续考虑增加功能
+                            Frame::TtsFrame(frame) => match frame.state {
+                                TtsState::Start => {
+                                    Self::transition(&state, DeviceState::Speaking).await
+                                }
+                                TtsState::Stop => {
+                                    emotion = None;
+                                    Self::transition(&state, DeviceState::Listening).await
+                                }
+                                TtsState::SentenceStart => {
+                                    if let Some(text) = frame.text {
+                                        state.read().await.emit("recv_text", text.clone());
+                                        debug!("对话文本: {}", text);
+                                        Self::record_history(
+                                            &state,
+                                            &ws,
+                                            Role::Assistant,
+                                            text,
+                                            emotion.clone(),
+                                        )
+                                        .await;
+                                    } else {
+                                        error!("对话文本为空");
                                     }
-                                    TtsState::SentenceStart => {
-                                        if let Some(text) = frame.text {
-                                            // XXX: 测试
-                                            if let Some(webview) = webview.as_ref() {
-                                                webview.emit("recv_text", text.clone()).unwrap();
-                                            }
-                                            debug!("对话文本: {}", text);
-                                        } else {
-                                            error!("对话文本为空");
-                                        }
 
-                                        audio_cache.write().await.session_stop().await;
-                                        audio_cache.write().await.session_start();
-                                        debug!("句子开始");
-                                    }
-                                    TtsState::SentenceEnd => debug!("句子结束"),
+                                    audio_cache.write().await.session_stop().await;
+                                    audio_cache.write().await.session_start();
+                                    debug!("句子开始");
                                 }
+                                TtsState::SentenceEnd => debug!("句子结束"),
+                            },
+                            Frame::SttFrame(frame) => {
+                                debug!("识别文本: {}", frame.text);
+                                // 新一轮对话, 上一轮的情绪不再适用
+                                emotion = None;
+                                Self::transition(&state, DeviceState::Thinking).await;
+                                Self::record_history(&state, &ws, Role::User, frame.text, None)
+                                    .await;
+                            }
+                            Frame::LlmFrame(frame) => {
+                                debug!("情绪: {:?}", frame.emotion);
+                                emotion = frame.emotion;
+                            }
+                            Frame::ListenFrame(frame) => match frame.state {
+                                ListenState::Start => {
+                                    Self::transition(&state, DeviceState::Listening).await
+                                }
+                                ListenState::Stop | ListenState::Detect => {
+                                    Self::transition(&state, DeviceState::Thinking).await
+                                }
+                            },
+                            Frame::GoodbyeFrame(_frame) => debug!("服务器结束会话"),
+                            Frame::Unknown { kind, raw } => {
+                                if hook::dispatch(&kind, &raw).not() {
+                                    debug!("未知帧类型:\n{:#}", raw);
+                                }
+                            }
+                            Frame::Invalid { raw, error } => {
+                                warn!("控制帧解析失败: {}\n{:#}", error, raw);
                             }
-                            Frame::ListenFrame(_frame) => {}
-                            Frame::Error => {}
                         }
                     };
                 }
             }));
     }
 
+    /// 由服务器帧驱动的状态切换, 非法切换仅记录日志
+    async fn transition(state: &SharedAsyncRwLock<StateMachine>, to: DeviceState) {
+        if let Err(e) = state.write().await.transition(to) {
+            warn!("{}", e);
+        }
+    }
+
+    /// 写入发起对话的窗口所属应用的历史记录
+    pub(super) async fn record_history(
+        state: &SharedAsyncRwLock<StateMachine>,
+        ws: &SharedTransport,
+        role: Role,
+        text: String,
+        emotion: Option<String>,
+    ) {
+        let Some(webview) = state.read().await.webview() else {
+            return;
+        };
+        let Some(history) = webview.try_state::<History>() else {
+            return;
+        };
+        let Some(session_id) = ws.read().await.get_session_id().await else {
+            warn!("未查询到 session_id, 跳过历史记录");
+            return;
+        };
+        history
+            .append(&HistoryEntry::new(&session_id, role, text, emotion))
+            .unwrap_or_else(|e| error!("历史记录写入失败: {}", e));
+    }
+
+    /// 等待工作线程退出, 需先结束对话
     pub(crate) async fn close(&mut self) {
-        if *self.is_stopped.read().await {
+        if self.worker_thread.is_none() {
             warn!("已拒绝重复停止控制器工作线程");
             return;
         }
-        self.is_stopped.write().await.clone_from(&true);
-
+        if self.activity.as_ref().is_some_and(Activity::is_active) {
+            warn!("对话仍在进行，控制器工作线程将在对话结束后退出");
+            return;
+        }
         if let Some(wt) = self.worker_thread.take() {
             if let Err(e) = wt.await {
                 error!("控制器工作线程停止失败: {}", e);
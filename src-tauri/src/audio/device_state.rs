@@ -0,0 +1,238 @@
+//! 对话状态机
+//!
+//! - `Idle -> Connecting -> Listening`: 开始对话
+//! - `Listening -> Thinking`: 识别到用户说话结束, 或发送文本消息
+//! - `Listening | Thinking -> Speaking`: 服务器开始 TTS
+//! - `Speaking -> Listening`: TTS 结束
+//! - 任意活动状态均可回到 Idle 或进入 Error
+
+use serde::Serialize;
+use std::fmt::Display;
+use std::ops::Not;
+use tauri::Emitter;
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceState {
+    Idle,
+    Connecting,
+    Listening,
+    Thinking,
+    Speaking,
+    Error,
+}
+
+impl DeviceState {
+    fn can_transition(self, to: Self) -> bool {
+        use DeviceState::*;
+        matches!(
+            (self, to),
+            (Idle, Connecting)
+                | (Connecting, Listening | Idle | Error)
+                | (Listening, Thinking | Speaking | Idle | Error)
+                | (Thinking, Listening | Speaking | Idle | Error)
+                | (Speaking, Listening | Thinking | Idle | Error)
+                | (Error, Idle | Connecting)
+        )
+    }
+
+    /// 对话进行中
+    pub fn is_active(self) -> bool {
+        matches!(
+            self,
+            DeviceState::Connecting
+                | DeviceState::Listening
+                | DeviceState::Thinking
+                | DeviceState::Speaking
+        )
+    }
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DeviceState::Idle => "idle",
+            DeviceState::Connecting => "connecting",
+            DeviceState::Listening => "listening",
+            DeviceState::Thinking => "thinking",
+            DeviceState::Speaking => "speaking",
+            DeviceState::Error => "error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    InvalidTransition {
+        from: DeviceState,
+        to: DeviceState,
+    },
+    /// 对话已开始, 拒绝再次启动
+    AlreadyActive(DeviceState),
+    /// 对话未开始
+    NotActive,
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::InvalidTransition { from, to } => {
+                write!(f, "非法的状态切换: {} -> {}", from, to)
+            }
+            StateError::AlreadyActive(state) => write!(f, "对话已开始 ({})", state),
+            StateError::NotActive => write!(f, "对话未开始"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// `device_state` 事件载荷
+#[derive(Serialize, Debug, Clone)]
+struct StateChanged {
+    state: DeviceState,
+    previous: DeviceState,
+}
+
+/// 发往前端的事件, 也可在进程内订阅
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: String,
+    pub payload: serde_json::Value,
+}
+
+/// 对话是否进行中, 由状态机派生, 各工作线程据此判断何时退出
+///
+/// 状态机释放后视为对话已结束
+#[derive(Clone)]
+pub struct Activity(watch::Receiver<DeviceState>);
+
+impl Activity {
+    pub fn is_active(&self) -> bool {
+        self.0.has_changed().is_ok() && self.0.borrow().is_active()
+    }
+}
+
+pub struct StateMachine {
+    state: DeviceState,
+    webview: Option<tauri::WebviewWindow>,
+    watch: watch::Sender<DeviceState>,
+    events: broadcast::Sender<Event>,
+}
+
+impl StateMachine {
+    pub(super) fn new() -> Self {
+        Self {
+            state: DeviceState::Idle,
+            webview: None,
+            watch: watch::Sender::new(DeviceState::Idle),
+            events: broadcast::Sender::new(64),
+        }
+    }
+
+    pub fn state(&self) -> DeviceState {
+        self.state
+    }
+
+    pub(super) fn activity(&self) -> Activity {
+        Activity(self.watch.subscribe())
+    }
+
+    /// 设置发起对话的窗口, 为空时保留原窗口
+    pub(super) fn set_webview(&mut self, webview: Option<tauri::WebviewWindow>) {
+        if webview.is_some() {
+            self.webview = webview;
+        }
+    }
+
+    pub(super) fn webview(&self) -> Option<tauri::WebviewWindow> {
+        self.webview.clone()
+    }
+
+    /// 订阅此后发出的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// 切换状态并通知前端, 切换到当前状态视为成功且不通知
+    pub fn transition(&mut self, to: DeviceState) -> Result<(), StateError> {
+        if self.state == to {
+            return Ok(());
+        }
+        if self.state.can_transition(to).not() {
+            return Err(StateError::InvalidTransition {
+                from: self.state,
+                to,
+            });
+        }
+        let previous = std::mem::replace(&mut self.state, to);
+        self.watch.send_replace(to);
+        debug!("状态切换: {} -> {}", previous, to);
+
+        self.emit(
+            "device_state",
+            StateChanged {
+                state: to,
+                previous,
+            },
+        );
+        Ok(())
+    }
+
+    /// 向发起对话的窗口及进程内订阅者发送事件
+    pub(super) fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if self.events.receiver_count() > 0 {
+            match serde_json::to_value(payload.clone()) {
+                Ok(payload) => {
+                    self.events
+                        .send(Event {
+                            name: event.to_string(),
+                            payload,
+                        })
+                        .ok();
+                }
+                Err(e) => error!("{} 事件序列化失败: {}", event, e),
+            }
+        }
+        if let Some(webview) = self.webview.as_ref() {
+            webview
+                .emit(event, payload)
+                .unwrap_or_else(|e| error!("{} 事件发送失败: {}", event, e));
+        }
+    }
+}
+
+#[test]
+fn f() {
+    use DeviceState::*;
+
+    let mut machine = StateMachine::new();
+    let activity = machine.activity();
+    for state in [Connecting, Listening, Thinking, Speaking, Listening, Idle] {
+        machine.transition(state).unwrap();
+    }
+    assert_eq!(machine.state(), Idle);
+
+    assert_eq!(
+        machine.transition(Speaking),
+        Err(StateError::InvalidTransition {
+            from: Idle,
+            to: Speaking
+        })
+    );
+    assert_eq!(machine.state(), Idle);
+    assert!(machine.transition(Idle).is_ok());
+
+    machine.transition(Connecting).unwrap();
+    machine.transition(Error).unwrap();
+    assert!(machine.transition(Listening).is_err());
+    machine.transition(Connecting).unwrap();
+    assert!(activity.is_active());
+    assert!(Connecting.is_active());
+    assert!(Error.is_active().not());
+    drop(machine);
+    assert!(activity.is_active().not());
+}
@@ -1,10 +1,15 @@
 use crate::{
-    audio::cache::AudioCache,
-    types::{SharedAsyncMutex, SharedAsyncRwLock},
+    audio::{
+        cache::AudioCache,
+        device_state::Activity,
+        supervisor::{Supervisor, Worker},
+    },
+    error::AppError,
+    types::SharedAsyncRwLock,
     utils::{config::Config, device::get_device},
 };
 use cpal::traits::{DeviceTrait, StreamTrait};
-use tracing::error;
+use std::ops::Not;
 
 fn input_callback(
     audio_cacahe: SharedAsyncRwLock<AudioCache>,
@@ -21,60 +26,79 @@ fn input_callback(
 }
 
 pub(super) async fn input(
-    stopflag: SharedAsyncRwLock<bool>,
+    activity: Activity,
     audio_cacahe: SharedAsyncRwLock<AudioCache>,
-) {
-    let stream = SharedAsyncMutex::new(
-        get_device(crate::utils::device::DeviceType::Input)
-            .inspect_err(|e| error!("获取输入设备失败: {}", e))
-            .unwrap()
-            .build_input_stream(
-                &Config::get_instance()
-                    .input_device
-                    .raw_config
-                    .clone()
-                    .unwrap()
-                    .into(),
-                input_callback(audio_cacahe),
-                |e| {
-                    error!("Error: {}", e);
-                },
-                None,
-            )
-            .unwrap()
-            .into(),
-    );
+    supervisor: Supervisor,
+) -> Result<(), AppError> {
+    let config = Config::get_instance();
+    let Some(stream_config) = config.input_device.raw_config.clone() else {
+        return Err(AppError::DeviceUnavailable(
+            "未获取到输入设备配置".to_string(),
+        ));
+    };
+    let stream = get_device(
+        crate::utils::device::DeviceType::Input,
+        config.device.input.as_deref(),
+    )
+    .map_err(|e| AppError::DeviceUnavailable(format!("获取输入设备失败: {}", e)))?
+    .build_input_stream(
+        &stream_config.into(),
+        input_callback(audio_cacahe),
+        stream_error(supervisor, Worker::Input),
+        None,
+    )
+    .map_err(|e| AppError::DeviceUnavailable(format!("创建输入流失败: {}", e)))?;
 
     stream
-        .lock()
-        .await
         .play()
-        .inspect_err(|e| error!("播放失败: {}", e))
-        .unwrap();
+        .map_err(|e| AppError::DeviceUnavailable(format!("录音失败: {}", e)))?;
 
+    wait_stop(activity, stream).await
+}
+
+/// 音频流错误 (如设备被拔出) 上报后由 `AudioState_` 结束对话
+fn stream_error(
+    supervisor: Supervisor,
+    worker: Worker,
+) -> impl FnMut(cpal::StreamError) + Send + 'static {
+    move |e| {
+        supervisor.report(
+            worker,
+            AppError::DeviceUnavailable(format!("音频流错误: {}", e)),
+        )
+    }
+}
+
+/// 等待对话结束并暂停音频流
+async fn wait_stop(activity: Activity, stream: cpal::Stream) -> Result<(), AppError> {
     loop {
-        if *stopflag.read().await {
+        if activity.is_active().not() {
             stream
-                .lock()
-                .await
                 .pause()
-                .inspect_err(|e| error!("暂停失败: {}", e))
-                .unwrap();
-            break;
+                .map_err(|e| AppError::DeviceUnavailable(format!("暂停失败: {}", e)))?;
+            return Ok(());
         }
         std::thread::sleep(std::time::Duration::from_millis(200));
     }
 }
 
+/// 不使用音频设备时, 仅等待对话结束
+pub(super) fn idle(activity: Activity) -> Result<(), AppError> {
+    while activity.is_active() {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+    }
+    Ok(())
+}
+
 fn output_callback(
-    stopflag: SharedAsyncRwLock<bool>,
+    activity: Activity,
     audio_cacahe: SharedAsyncRwLock<AudioCache>,
 ) -> impl FnMut(&mut [i16], &cpal::OutputCallbackInfo) {
     move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
         tauri::async_runtime::block_on(async {
             let mut n = 0;
             loop {
-                if *stopflag.read().await {
+                if activity.is_active().not() {
                     break;
                 }
 
@@ -94,47 +118,32 @@ fn output_callback(
 }
 
 pub(super) async fn output(
-    stopflag: SharedAsyncRwLock<bool>,
+    activity: Activity,
     audio_cacahe: SharedAsyncRwLock<AudioCache>,
-) {
-    let stream = SharedAsyncMutex::new(
-        get_device(crate::utils::device::DeviceType::Output)
-            .inspect_err(|e| error!("获取输出设备失败: {}", e))
-            .unwrap()
-            .build_output_stream(
-                &Config::get_instance()
-                    .output_device
-                    .raw_config
-                    .clone()
-                    .unwrap()
-                    .into(),
-                output_callback(stopflag.clone(), audio_cacahe),
-                |e| {
-                    error!("Error: {}", e);
-                },
-                None,
-            )
-            .unwrap()
-            .into(),
-    );
+    supervisor: Supervisor,
+) -> Result<(), AppError> {
+    let config = Config::get_instance();
+    let Some(stream_config) = config.output_device.raw_config.clone() else {
+        return Err(AppError::DeviceUnavailable(
+            "未获取到输出设备配置".to_string(),
+        ));
+    };
+    let stream = get_device(
+        crate::utils::device::DeviceType::Output,
+        config.device.output.as_deref(),
+    )
+    .map_err(|e| AppError::DeviceUnavailable(format!("获取输出设备失败: {}", e)))?
+    .build_output_stream(
+        &stream_config.into(),
+        output_callback(activity.clone(), audio_cacahe),
+        stream_error(supervisor, Worker::Output),
+        None,
+    )
+    .map_err(|e| AppError::DeviceUnavailable(format!("创建输出流失败: {}", e)))?;
 
     stream
-        .lock()
-        .await
         .play()
-        .inspect_err(|e| error!("播放失败: {}", e))
-        .unwrap();
+        .map_err(|e| AppError::DeviceUnavailable(format!("播放失败: {}", e)))?;
 
-    loop {
-        if *stopflag.read().await {
-            stream
-                .lock()
-                .await
-                .pause()
-                .inspect_err(|e| error!("暂停失败: {}", e))
-                .unwrap();
-            break;
-        }
-        std::thread::sleep(std::time::Duration::from_millis(200));
-    }
+    wait_stop(activity, stream).await
 }
@@ -1,18 +1,40 @@
+#[allow(clippy::module_inception)]
 pub mod audio;
 pub mod cache;
+pub mod codec;
 pub mod controller;
+pub mod device_state;
 mod func;
+pub mod recorder;
+pub mod supervisor;
+pub mod vad;
 
 use std::ops::Not;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
 
 use crate::{
+    error::AppError,
     types::SharedAsyncRwLock,
-    utils::{config::Config, ws::WebsocketProtocol},
+    utils::{
+        config::Config,
+        frame::client::ClientMessage,
+        history::Role,
+        queue::Dropped,
+        transport::{self, SharedTransport, Transport},
+    },
 };
 use audio::Audio;
 use cache::AudioCache;
 use controller::Controller;
-use tracing::{debug, info, warn};
+use device_state::{DeviceState, Event, StateError, StateMachine};
+use supervisor::Supervisor;
+use tracing::{debug, error, info, warn};
+
+/// 服务器结束会话后, 等待剩余下行音频播放的最长时间
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 //
 //
 //
@@ -22,93 +44,404 @@ pub struct AudioState_ {
     audio: SharedAsyncRwLock<Audio>,
     audio_cache: SharedAsyncRwLock<AudioCache>,
     controller: SharedAsyncRwLock<Controller>,
-    ws: SharedAsyncRwLock<WebsocketProtocol>,
-    stopped: SharedAsyncRwLock<bool>,
+    ws: SharedTransport,
+    /// 传输层按配置创建, 配置变更时重建; 由调用方指定时始终沿用
+    transport_from_config: bool,
+    state: SharedAsyncRwLock<StateMachine>,
+    /// 本轮对话是否打开了麦克风, 配置变更重新开始时沿用
+    microphone: bool,
+    /// 客户端主动结束对话的通知
+    stop_notify: Arc<Notify>,
+    /// 串行化对话的启动与清理, 清理开始时状态已离开活动状态, 但工作线程可能尚未退出
+    lifecycle: Arc<Mutex<()>>,
 }
 
 impl AudioState_ {
     pub async fn new() -> Self {
+        let mut state = Self::with_transport(transport::from_config(&Config::get_instance())).await;
+        state.transport_from_config = true;
+        state
+    }
+
+    /// 使用指定的传输层, 配置变更时不重建
+    pub async fn with_transport(transport: Box<dyn Transport>) -> Self {
         Self {
             audio: SharedAsyncRwLock::new(Audio::new().into()),
             audio_cache: SharedAsyncRwLock::new(AudioCache::new().into()),
             controller: SharedAsyncRwLock::new(Controller::new().into()),
-            ws: SharedAsyncRwLock::new(
-                WebsocketProtocol::new(Config::get_instance().websocket.url.clone()).into(),
-            ),
-            stopped: SharedAsyncRwLock::new(true.into()),
+            ws: SharedAsyncRwLock::new(transport.into()),
+            transport_from_config: false,
+            state: SharedAsyncRwLock::new(StateMachine::new().into()),
+            microphone: true,
+            stop_notify: Arc::new(Notify::new()),
+            lifecycle: Arc::new(Mutex::new(())),
         }
     }
 
-    pub async fn ws_connect(&self) -> Result<String, String> {
-        self.ws
-            .write()
-            .await
-            .connect()
-            .await
-            .map_err(|e| e.to_string())
+    #[cfg(test)]
+    fn without_devices(mut self) -> Self {
+        self.audio = SharedAsyncRwLock::new(Audio::without_devices().into());
+        self
+    }
+
+    /// 订阅此后发往前端的事件
+    pub async fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Event> {
+        self.state.read().await.subscribe()
+    }
+
+    pub async fn ws_connect(&self) -> Result<String, AppError> {
+        self.ws.write().await.connect().await
     }
 
-    pub async fn start(&mut self, webview: Option<tauri::WebviewWindow>) -> Result<(), String> {
-        if self.stopped.read().await.not() {
+    pub async fn start(&mut self, webview: Option<tauri::WebviewWindow>) -> Result<(), AppError> {
+        self.start_session(webview, true).await
+    }
+
+    /// 开始对话, `microphone` 为假时不打开麦克风, 仅播放回复
+    async fn start_session(
+        &mut self,
+        webview: Option<tauri::WebviewWindow>,
+        microphone: bool,
+    ) -> Result<(), AppError> {
+        let _lifecycle = self.lifecycle.clone().lock_owned().await;
+        let current = self.state.read().await.state();
+        if current.is_active() {
             debug!("对话已开始，拒绝再次启动");
-            return Ok(());
+            return Err(StateError::AlreadyActive(current).into());
         }
+        self.state.write().await.set_webview(webview);
+        self.microphone = microphone;
+        // 配置无效时逐项返回给前端, 不建立连接
+        Config::get_instance().validate()?;
+        self.transition(DeviceState::Connecting).await?;
 
         if self.ws.read().await.is_connected().await.not() {
-            // XXX id 留存，如果需要使用
             debug!("WebSocket 连接中...");
-            let _id = self
-                .ws_connect()
-                .await
-                .inspect_err(|e| debug!("WebSocket 连接失败: {}", e))?;
+            if let Err(e) = self.ws_connect().await {
+                debug!("WebSocket 连接失败: {}", e);
+                self.transition(DeviceState::Error).await.ok();
+                return Err(e);
+            }
+        }
+        let (supervisor, mut failures) = Supervisor::channel();
+        let activity = self.state.read().await.activity();
+        if let Err(e) = AudioCache::start(
+            self.audio_cache.clone(),
+            self.ws.clone(),
+            supervisor.clone(),
+            activity.clone(),
+        )
+        .await
+        {
+            error!("创建编解码器失败: {}", e);
+            self.ws.write().await.close().await.ok();
+            self.transition(DeviceState::Error).await.ok();
+            return Err(e);
+        }
+        if let Some(id) = self.ws.read().await.get_session_id().await {
+            self.audio_cache.read().await.record_start(&id).await;
         }
-        AudioCache::start(self.audio_cache.clone(), self.ws.clone()).await;
-        Audio::start(self.audio.clone(), self.audio_cache.clone()).await;
+        Audio::start(
+            self.audio.clone(),
+            self.audio_cache.clone(),
+            supervisor.clone(),
+            activity,
+            microphone,
+        )
+        .await;
         Controller::start(
             self.controller.clone(),
             self.audio_cache.clone(),
             self.ws.clone(),
-            webview,
+            self.state.clone(),
+            supervisor,
         )
         .await;
 
         let ws_closed_notify = self.ws.read().await.get_closed_notify();
+        // 每轮对话使用新的通知, 避免上一轮残留的许可
+        self.stop_notify = Arc::new(Notify::new());
+        let stop_notify = self.stop_notify.clone();
         let controller = self.controller.clone();
         let audio = self.audio.clone();
         let audio_cache = self.audio_cache.clone();
         let ws = self.ws.clone();
-        let stopped = self.stopped.clone();
+        let state = self.state.clone();
+        let lifecycle = self.lifecycle.clone();
+        // 先进入 Listening, 避免启动阶段的故障先于此切换完成
+        self.transition(DeviceState::Listening).await?;
         tauri::async_runtime::spawn(async move {
-            ws_closed_notify.notified().await;
-            tokio::time::sleep(tokio::time::Duration::from_millis(900)).await;
-            info!("WebSocket 断开连接，准备清理资源");
-            stopped.write().await.clone_from(&true);
-            controller.write().await.close().await;
-            audio.write().await.close().await;
-            audio_cache.write().await.reset().await;
-            ws.write()
-                .await
-                .close()
+            let final_state = tokio::select! {
+                _ = ws_closed_notify.notified() => {
+                    info!("服务器结束会话，等待剩余音频播放完毕");
+                    AudioCache::drain(audio_cache.clone(), DRAIN_TIMEOUT).await;
+                    DeviceState::Idle
+                }
+                Some(failure) = failures.recv() => {
+                    error!("音频管线故障，结束对话: {}", failure.error);
+                    state.read().await.emit("audio_error", &failure);
+                    // 连接可能仍然可用, 通知服务器结束会话
+                    let session_id = ws.read().await.get_session_id().await;
+                    Self::send_message(&ws, &audio_cache, ClientMessage::goodbye(session_id))
+                        .await
+                        .ok();
+                    DeviceState::Error
+                }
+                // 客户端主动结束, 由 stop 负责清理
+                _ = stop_notify.notified() => return,
+            };
+            info!("准备清理资源");
+            let _lifecycle = lifecycle.lock().await;
+            Self::teardown(state, controller, audio, audio_cache, ws, final_state)
                 .await
                 .inspect_err(|e| warn!("WebSocket 关闭失败: {}", e))
                 .ok();
             info!("资源清理完成，已停止对话");
         });
-        self.stopped.write().await.clone_from(&false);
         Ok(())
     }
 
-    pub async fn stop(&self) -> Result<(), String> {
-        if *self.stopped.read().await {
+    pub async fn stop(&self) -> Result<(), AppError> {
+        if self.state.read().await.state().is_active().not() {
             warn!("对话已结束，无需再次停止");
+            return Err(StateError::NotActive.into());
+        }
+        self.stop_notify.notify_one();
+
+        // 通知服务器结束会话, 连接可能已断开, 失败不影响清理
+        let session_id = self.ws.read().await.get_session_id().await;
+        Self::send_message(
+            &self.ws,
+            &self.audio_cache,
+            ClientMessage::goodbye(session_id),
+        )
+        .await
+        .inspect_err(|e| warn!("发送 goodbye 失败: {}", e))
+        .ok();
+
+        let _lifecycle = self.lifecycle.lock().await;
+        Self::teardown(
+            self.state.clone(),
+            self.controller.clone(),
+            self.audio.clone(),
+            self.audio_cache.clone(),
+            self.ws.clone(),
+            DeviceState::Idle,
+        )
+        .await
+    }
+
+    /// 先切换到结束状态, 各工作线程据此退出, 再等待其退出并释放资源
+    /// 发送控制消息, 会话录制开启时记录为上行帧
+    async fn send_message(
+        ws: &SharedTransport,
+        audio_cache: &SharedAsyncRwLock<AudioCache>,
+        message: ClientMessage,
+    ) -> Result<(), AppError> {
+        ws.read().await.send_message(message.clone()).await?;
+        audio_cache
+            .read()
+            .await
+            .record_frame(recorder::Direction::Up, &message)
+            .await;
+        Ok(())
+    }
+
+    async fn teardown(
+        state: SharedAsyncRwLock<StateMachine>,
+        controller: SharedAsyncRwLock<Controller>,
+        audio: SharedAsyncRwLock<Audio>,
+        audio_cache: SharedAsyncRwLock<AudioCache>,
+        ws: SharedTransport,
+        final_state: DeviceState,
+    ) -> Result<(), AppError> {
+        state.write().await.transition(final_state)?;
+        controller.write().await.close().await;
+        audio.write().await.close().await;
+        audio_cache.write().await.reset().await;
+        ws.write().await.close().await
+    }
+
+    /// 应用新配置: 重建受影响的子系统, 进行中的对话在重建后重新开始
+    pub async fn apply_config(&mut self, old: &Config, new: &Config) -> Result<(), AppError> {
+        if old.websocket == new.websocket
+            && old.mqtt == new.mqtt
+            && old.opus == new.opus
+            && old.device == new.device
+            && old.profile == new.profile
+        {
             return Ok(());
         }
-        self.ws.read().await.get_closed_notify().notify_waiters();
-        self.controller.write().await.close().await;
-        self.audio.write().await.close().await;
-        self.audio_cache.write().await.reset().await;
-        self.ws.write().await.close().await?;
-        self.stopped.write().await.clone_from(&true);
+
+        let active = self.device_state().await.is_active();
+        // 重新开始时沿用发起对话的窗口
+        let webview = self.state.read().await.webview();
+        if active {
+            info!("配置已变更，重新开始对话");
+            self.stop().await?;
+        }
+
+        if self.transport_from_config
+            && (old.websocket != new.websocket
+                || old.mqtt != new.mqtt
+                || old.opus != new.opus
+                || old.profile != new.profile)
+        {
+            *self.ws.write().await = transport::from_config(new);
+        }
+        *self.audio_cache.write().await = AudioCache::new();
+
+        if active {
+            self.start_session(webview, self.microphone).await?;
+        }
+        Ok(())
+    }
+
+    /// 连接的往返时延, 未连接或传输层不发送心跳时为空
+    pub async fn rtt(&self) -> Option<Duration> {
+        self.ws.read().await.get_rtt().await
+    }
+
+    /// 传输层与音频缓存满时丢弃的数据量
+    pub async fn dropped(&self) -> Dropped {
+        self.ws.read().await.dropped() + self.audio_cache.read().await.dropped()
+    }
+
+    pub async fn device_state(&self) -> DeviceState {
+        self.state.read().await.state()
+    }
+
+    async fn transition(&self, to: DeviceState) -> Result<(), AppError> {
+        Ok(self.state.write().await.transition(to)?)
+    }
+
+    /// 以文本代替语音发起一轮对话, 回复仍以文本和语音两种形式呈现
+    pub async fn send_text_message(
+        &mut self,
+        text: String,
+        webview: Option<tauri::WebviewWindow>,
+    ) -> Result<(), AppError> {
+        if text.trim().is_empty() {
+            return Err(AppError::InvalidInput("消息内容为空".to_string()));
+        }
+
+        // 对话未开始时先启动, 以便播放回复语音, 不打开麦克风
+        if self.device_state().await.is_active().not() {
+            self.start_session(webview, false).await?;
+        }
+
+        let session_id = self.ws.read().await.get_session_id().await;
+        Self::send_message(
+            &self.ws,
+            &self.audio_cache,
+            ClientMessage::listen_detect(session_id, text.clone()),
+        )
+        .await?;
+        debug!("发送文本消息: {}", text);
+        self.transition(DeviceState::Thinking).await?;
+
+        Controller::record_history(&self.state, &self.ws, Role::User, text, None).await;
         Ok(())
     }
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn f() {
+    use crate::utils::device::DeviceConfig;
+    use crate::utils::loopback::{self, Uplink};
+    use crate::utils::transport::AudioPacket;
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    async fn wait_event(
+        events: &mut broadcast::Receiver<Event>,
+        f: impl Fn(&Event) -> bool,
+    ) -> Event {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if f(&event) {
+                    break event;
+                }
+            }
+        })
+        .await
+        .expect("等待事件超时")
+    }
+
+    let (transport, mut peer) = loopback::pair("loopback");
+    let mut state = AudioState_::with_transport(Box::new(transport))
+        .await
+        .without_devices();
+    // 测试环境没有音频设备, 按 16 kHz 单声道处理
+    let mut config = (*Config::get_instance()).clone();
+    let device = DeviceConfig {
+        sample_rate: 16000,
+        channels: 1,
+        raw_config: None,
+    };
+    config.input_device = device.clone();
+    config.output_device = device;
+    *state.audio_cache.write().await = AudioCache::with_config(Arc::new(config));
+    let mut events = state.subscribe().await;
+    state.start(None).await.unwrap();
+
+    // 上行: 采样经 AudioCache 编码后发送
+    state
+        .audio_cache
+        .read()
+        .await
+        .write_input_data(vec![0.1; 3200])
+        .await;
+    let uplink = tokio::time::timeout(Duration::from_secs(2), peer.recv())
+        .await
+        .unwrap();
+    assert!(matches!(uplink, Some(Uplink::Audio(_))));
+
+    // 下行: 控制帧由 Controller 处理, 音频进入 AudioCache
+    peer.send_frame(json!({"type": "tts", "state": "start"}));
+    wait_event(&mut events, |e| {
+        e.name == "device_state" && e.payload["state"] == "speaking"
+    })
+    .await;
+    peer.send_audio_packet(AudioPacket::new(vec![1; 10], Some(60)));
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while state.audio_cache.read().await.output_pending().await == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // 对话中修改配置, 重新开始后沿用指定的传输层, 事件仍送达
+    let old = Config::get_instance();
+    let mut new = (*old).clone();
+    new.websocket.url = "ws://127.0.0.1:1".to_string();
+    new.device.input = Some("test".to_string());
+    state.apply_config(&old, &new).await.unwrap();
+    assert_eq!(state.device_state().await, DeviceState::Listening);
+
+    peer.send_frame(json!({"type": "tts", "state": "sentence_start", "text": "你好"}));
+    let event = wait_event(&mut events, |e| e.name == "recv_text").await;
+    assert_eq!(event.payload, "你好");
+
+    state.stop().await.unwrap();
+    assert_eq!(state.device_state().await, DeviceState::Idle);
+
+    // 文本对话只启动传输层与播放
+    while peer.try_recv().is_some() {}
+    state
+        .send_text_message("你好".to_string(), None)
+        .await
+        .unwrap();
+    assert_eq!(state.device_state().await, DeviceState::Thinking);
+    let Some(Uplink::Text(text)) = peer.recv().await else {
+        panic!("未收到文本消息");
+    };
+    assert!(text.contains("detect") && text.contains("你好"));
+    {
+        let audio = state.audio.read().await;
+        assert!(audio.audioInThread.is_none() && audio.audioOutThread.is_some());
+    }
+    state.stop().await.unwrap();
+}
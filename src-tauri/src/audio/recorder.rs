@@ -0,0 +1,248 @@
+//! 会话录制: 将上下行 Opus 数据分别写入 Ogg-Opus 文件, 控制帧写入 JSON Lines 旁路文件
+//!
+//! 目录结构:
+//! ```text
+//! <dir>/<session_id>/uplink.opus
+//! <dir>/<session_id>/downlink.opus
+//! <dir>/<session_id>/frames.jsonl
+//! ```
+
+use crate::audio::codec;
+use crate::utils::frame::client::AudioParams;
+use crate::utils::history::is_valid_session_id;
+use anyhow::anyhow;
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Not;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const UPLINK_FILE: &str = "uplink.opus";
+pub const DOWNLINK_FILE: &str = "downlink.opus";
+pub const FRAMES_FILE: &str = "frames.jsonl";
+
+/// Ogg granule position 固定以 48kHz 计
+const GRANULE_RATE: u32 = 48000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// 客户端 -> 服务器
+    Up,
+    /// 服务器 -> 客户端
+    Down,
+}
+
+struct OggOpusWriter {
+    writer: PacketWriter<BufWriter<File>>,
+    serial: u32,
+    granule: u64,
+    /// 最后一个数据包需要标记流结束, 因此延迟一个包写入
+    pending: Option<Vec<u8>>,
+}
+
+impl OggOpusWriter {
+    fn create(path: &Path, serial: u32, params: &AudioParams) -> std::io::Result<Self> {
+        let mut writer = PacketWriter::new(BufWriter::new(File::create(path)?));
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1);
+        head.push(params.channels as u8);
+        head.extend_from_slice(&0u16.to_le_bytes());
+        head.extend_from_slice(&params.sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+        writer.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = env!("CARGO_PKG_NAME").as_bytes();
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            writer,
+            serial,
+            granule: 0,
+            pending: None,
+        })
+    }
+
+    fn write(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        if let Some(prev) = self.pending.replace(packet.to_vec()) {
+            self.flush_packet(prev, PacketWriteEndInfo::NormalPacket)?;
+        }
+        Ok(())
+    }
+
+    fn flush_packet(&mut self, packet: Vec<u8>, info: PacketWriteEndInfo) -> std::io::Result<()> {
+        let samples = opus::packet::get_nb_samples(&packet, GRANULE_RATE).unwrap_or(0);
+        self.granule += samples as u64;
+        self.writer
+            .write_packet(packet.into(), self.serial, info, self.granule)
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        if let Some(last) = self.pending.take() {
+            self.flush_packet(last, PacketWriteEndInfo::EndStream)?;
+        }
+        self.writer.inner_mut().flush()
+    }
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    uplink: OggOpusWriter,
+    downlink: OggOpusWriter,
+    frames: BufWriter<File>,
+}
+
+impl Recorder {
+    /// 上下行音频参数可能不同, 分别写入各自的 OpusHead
+    pub fn new(
+        dir: &Path,
+        session_id: &str,
+        uplink: &AudioParams,
+        downlink: &AudioParams,
+    ) -> std::io::Result<Self> {
+        if is_valid_session_id(session_id).not() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("非法的会话ID: {}", session_id),
+            ));
+        }
+        let path = dir.join(session_id);
+        std::fs::create_dir_all(&path)?;
+
+        Ok(Self {
+            uplink: OggOpusWriter::create(&path.join(UPLINK_FILE), 1, uplink)?,
+            downlink: OggOpusWriter::create(&path.join(DOWNLINK_FILE), 2, downlink)?,
+            frames: BufWriter::new(File::create(path.join(FRAMES_FILE))?),
+            started: Instant::now(),
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_audio(&mut self, direction: Direction, packet: &[u8]) -> std::io::Result<()> {
+        match direction {
+            Direction::Up => self.uplink.write(packet),
+            Direction::Down => self.downlink.write(packet),
+        }
+    }
+
+    pub fn write_frame(&mut self, direction: Direction, frame: Value) -> std::io::Result<()> {
+        let line = json!({
+            "t_ms": self.started.elapsed().as_millis() as u64,
+            "dir": direction,
+            "frame": frame,
+        });
+        writeln!(self.frames, "{}", line)
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.uplink.finish()?;
+        self.downlink.finish()?;
+        self.frames.flush()
+    }
+}
+
+/// 读取录制的 Ogg-Opus 文件, 返回跳过 OpusHead/OpusTags 后的 Opus 数据包
+pub fn read_packets(path: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut reader = ogg::reading::PacketReader::new(std::io::BufReader::new(File::open(path)?));
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.read_packet()? {
+        if packet.data.starts_with(b"OpusHead") || packet.data.starts_with(b"OpusTags") {
+            continue;
+        }
+        packets.push(packet.data);
+    }
+    Ok(packets)
+}
+
+/// 读取录制时的音频参数, 采样率与声道数取自 OpusHead, 帧时长取自第一个数据包
+pub fn read_params(path: &Path) -> anyhow::Result<AudioParams> {
+    let mut reader = ogg::reading::PacketReader::new(std::io::BufReader::new(File::open(path)?));
+    let head = reader
+        .read_packet()?
+        .filter(|e| e.data.starts_with(b"OpusHead") && e.data.len() >= 19)
+        .ok_or(anyhow!("缺少 OpusHead: {}", path.display()))?;
+    let frame_duration = read_packets(path)?
+        .first()
+        .and_then(|e| opus::packet::get_nb_samples(e, GRANULE_RATE).ok())
+        .map(|e| e as u32 * 1000 / GRANULE_RATE)
+        .unwrap_or(AudioParams::default().frame_duration);
+    Ok(AudioParams {
+        format: codec::OPUS.to_string(),
+        sample_rate: u32::from_le_bytes(head.data[12..16].try_into()?),
+        channels: head.data[9] as u32,
+        frame_duration,
+    })
+}
+
+/// 读取录制的控制帧
+pub fn read_frames(path: &Path) -> anyhow::Result<Vec<Value>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|e| e.trim().is_empty().not())
+        .map(|e| Ok(serde_json::from_str(e)?))
+        .collect()
+}
+
+#[test]
+fn f() {
+    let dir = std::env::temp_dir().join(format!("t-app-recorder-test-{}", std::process::id()));
+    let uplink = AudioParams {
+        frame_duration: 20,
+        ..Default::default()
+    };
+    let downlink = AudioParams {
+        sample_rate: 24000,
+        channels: 2,
+        ..Default::default()
+    };
+    for id in ["", "../x", "/tmp/x", "a\\b"] {
+        assert!(
+            Recorder::new(&dir, id, &uplink, &downlink).is_err(),
+            "{}",
+            id
+        );
+    }
+    let mut recorder = Recorder::new(&dir, "session", &uplink, &downlink).unwrap();
+    // 静音 CELT 包: 20ms 单帧
+    let packet = vec![0xf8, 0xff, 0xfe];
+    for _ in 0..3 {
+        recorder.write_audio(Direction::Up, &packet).unwrap();
+    }
+    recorder
+        .write_frame(Direction::Up, json!({"type": "listen", "state": "detect"}))
+        .unwrap();
+    recorder
+        .write_frame(Direction::Down, json!({"type": "tts", "state": "start"}))
+        .unwrap();
+    let path = recorder.path().to_path_buf();
+    recorder.finish().unwrap();
+
+    let packets = read_packets(&path.join(UPLINK_FILE)).unwrap();
+    assert_eq!(packets, vec![packet; 3]);
+    assert!(read_packets(&path.join(DOWNLINK_FILE)).unwrap().is_empty());
+    assert_eq!(read_params(&path.join(UPLINK_FILE)).unwrap(), uplink);
+    assert_eq!(read_params(&path.join(DOWNLINK_FILE)).unwrap(), downlink);
+
+    let frames = read_frames(&path.join(FRAMES_FILE)).unwrap();
+    assert_eq!(frames[0]["frame"]["state"], "detect");
+    assert_eq!(frames[0]["dir"], "up");
+    assert_eq!(frames[1]["frame"]["state"], "start");
+    assert_eq!(frames[1]["dir"], "down");
+    std::fs::remove_dir_all(&dir).ok();
+}
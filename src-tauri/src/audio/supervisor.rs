@@ -0,0 +1,120 @@
+//! 音频管线监督: 各工作线程通过通道上报故障, 由 `AudioState_` 统一结束对话
+
+use crate::error::AppError;
+use futures_util::FutureExt;
+use serde::Serialize;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Worker {
+    /// 音频输入线程
+    Input,
+    /// 音频输出线程
+    Output,
+    /// 上行编码与发送
+    Encoder,
+    /// 下行解码与重采样
+    Decoder,
+    /// 控制帧处理
+    Controller,
+}
+
+/// `audio_error` 事件载荷
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkerFailure {
+    pub worker: Worker,
+    #[serde(flatten)]
+    pub error: AppError,
+}
+
+#[derive(Clone)]
+pub struct Supervisor(mpsc::UnboundedSender<WorkerFailure>);
+
+impl Supervisor {
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<WorkerFailure>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self(tx), rx)
+    }
+
+    pub fn report(&self, worker: Worker, error: AppError) {
+        error!("{:?} 工作线程故障: {}", worker, error);
+        if self.0.send(WorkerFailure { worker, error }).is_err() {
+            warn!("对话已结束, 故障未上报");
+        }
+    }
+
+    /// 运行工作线程主体, 将返回的错误与 panic 一并上报
+    pub fn run<F>(&self, worker: Worker, f: F)
+    where
+        F: FnOnce() -> Result<(), AppError>,
+    {
+        match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => self.report(worker, e),
+            Err(panic) => self.report(
+                worker,
+                AppError::Internal(format!("线程异常退出: {}", panic_message(&*panic))),
+            ),
+        }
+    }
+
+    /// 在异步运行时中运行工作任务, panic 时上报
+    pub fn spawn<F>(&self, worker: Worker, f: F) -> tauri::async_runtime::JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(panic) = AssertUnwindSafe(f).catch_unwind().await {
+                supervisor.report(
+                    worker,
+                    AppError::Internal(format!("任务异常退出: {}", panic_message(&*panic))),
+                );
+            }
+        })
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|e| e.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn f() {
+    let (supervisor, mut rx) = Supervisor::channel();
+    supervisor.run(Worker::Input, || Ok(()));
+    supervisor.run(Worker::Output, || {
+        Err(AppError::DeviceUnavailable("无输出设备".to_string()))
+    });
+    supervisor.run(Worker::Decoder, || panic!("解码失败"));
+
+    let failure = rx.try_recv().unwrap();
+    assert_eq!(failure.worker, Worker::Output);
+    let json = serde_json::to_value(&failure).unwrap();
+    assert_eq!(json["worker"], "output");
+    assert_eq!(json["code"], "DEVICE_UNAVAILABLE");
+
+    let failure = rx.try_recv().unwrap();
+    assert_eq!(failure.worker, Worker::Decoder);
+    assert!(failure.error.to_string().contains("解码失败"));
+    assert!(rx.try_recv().is_err());
+
+    supervisor
+        .spawn(Worker::Controller, async {
+            panic!("控制帧处理失败")
+        })
+        .await
+        .ok();
+    let failure = rx.try_recv().unwrap();
+    assert_eq!(failure.worker, Worker::Controller);
+    assert!(failure.error.to_string().contains("控制帧处理失败"));
+}
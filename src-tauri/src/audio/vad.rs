@@ -0,0 +1,70 @@
+//! 基于能量的简单语音活动检测
+
+/// 判定为语音的 RMS 阈值
+const THRESHOLD: f32 = 0.02;
+/// 持续静音超过该时长视为说话结束, 毫秒
+const HANGOVER_MS: u32 = 800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    SpeechStart,
+    SpeechEnd,
+}
+
+#[derive(Debug, Default)]
+pub struct Vad {
+    speaking: bool,
+    silence_ms: u32,
+    event: Option<VadEvent>,
+}
+
+impl Vad {
+    /// 输入一段音频, `duration_ms` 为该段时长
+    pub fn update(&mut self, samples: &[f32], duration_ms: u32) {
+        if samples.is_empty() {
+            return;
+        }
+        let rms = (samples.iter().map(|e| e * e).sum::<f32>() / samples.len() as f32).sqrt();
+
+        if rms > THRESHOLD {
+            self.silence_ms = 0;
+            if !self.speaking {
+                self.speaking = true;
+                self.event = Some(VadEvent::SpeechStart);
+            }
+        } else if self.speaking {
+            self.silence_ms += duration_ms;
+            if self.silence_ms >= HANGOVER_MS {
+                self.speaking = false;
+                self.silence_ms = 0;
+                self.event = Some(VadEvent::SpeechEnd);
+            }
+        }
+    }
+
+    /// 取出最近一次事件
+    pub fn take_event(&mut self) -> Option<VadEvent> {
+        self.event.take()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[test]
+fn f() {
+    let mut vad = Vad::default();
+    vad.update(&[0.0; 160], 10);
+    assert_eq!(vad.take_event(), None);
+
+    vad.update(&[0.5, -0.5].repeat(80), 10);
+    assert_eq!(vad.take_event(), Some(VadEvent::SpeechStart));
+
+    for _ in 0..(HANGOVER_MS / 100 - 1) {
+        vad.update(&[0.0; 160], 100);
+    }
+    assert_eq!(vad.take_event(), None);
+    vad.update(&[0.0; 160], 100);
+    assert_eq!(vad.take_event(), Some(VadEvent::SpeechEnd));
+}
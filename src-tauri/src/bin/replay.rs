@@ -0,0 +1,133 @@
+//! 将录制的上行音频与控制帧回放到服务器, 并与录制时收到的控制帧进行对比, 用于回归测试
+//!
+//! 连接参数取自当前配置 (含服务器配置中的 token、TLS、代理与协议版本), 音频参数取自录制文件
+//!
+//! 用法: replay <会话录制目录> [WebSocket URL]
+
+use app_lib::audio::recorder::{FRAMES_FILE, UPLINK_FILE, read_frames, read_packets, read_params};
+use app_lib::utils::{
+    config::Config, log::init_logger, transport::Transport, ws::WebsocketProtocol,
+};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::{error::Error, path::PathBuf, time::Duration};
+use tracing::{info, warn};
+
+/// 音频发送完毕后等待服务器响应的时长
+const TAIL_WAIT: Duration = Duration::from_secs(10);
+
+enum Uplink {
+    Audio(Vec<u8>),
+    Frame(Value),
+}
+
+/// 对比的帧序列: `type` 与 `state`, 其余字段 (session_id、文本等) 不参与对比
+fn kind(frame: &Value) -> String {
+    match frame["state"].as_str() {
+        Some(state) => format!("{} {}", frame["type"].as_str().unwrap_or_default(), state),
+        None => frame["type"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    init_logger();
+
+    let mut args = std::env::args().skip(1);
+    let dir = PathBuf::from(
+        args.next()
+            .ok_or("用法: replay <会话录制目录> [WebSocket URL]")?,
+    );
+    let mut config = (*Config::get_instance()).clone();
+    if let Some(url) = args.next() {
+        config.websocket.url = url;
+    }
+
+    let params = read_params(&dir.join(UPLINK_FILE))?;
+    let packets = read_packets(&dir.join(UPLINK_FILE))?;
+    let mut recorded = Vec::new();
+    // 按录制时的时间排列上行音频与控制帧, 音频包按帧时长依次排列
+    let mut timeline = packets
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| (i as u64 * params.frame_duration as u64, Uplink::Audio(e)))
+        .collect::<Vec<_>>();
+    for mut e in read_frames(&dir.join(FRAMES_FILE))? {
+        match e["dir"].as_str() {
+            Some("up") => timeline.push((
+                e["t_ms"].as_u64().unwrap_or(0),
+                Uplink::Frame(e["frame"].take()),
+            )),
+            _ => recorded.push(e["frame"].take()),
+        }
+    }
+    timeline.sort_by_key(|e| e.0);
+    info!(
+        "读取录制会话: 音频参数 {:?}, 上行 {} 条, 下行控制帧 {} 条",
+        params,
+        timeline.len(),
+        recorded.len()
+    );
+
+    let replayed = tauri::async_runtime::block_on(async {
+        let mut ws = WebsocketProtocol::from_config(&config).with_audio_params(params);
+        let id = ws.connect().await?;
+        info!("回放会话: {}", id);
+        let mut stream = ws.frames().await.ok_or("未取得控制帧流")?;
+
+        let mut frames = Vec::new();
+        let started = tokio::time::Instant::now();
+        for (t_ms, uplink) in timeline {
+            // 按录制时的时间发送, 模拟实时上行, 等待期间接收下行控制帧
+            let at = started + Duration::from_millis(t_ms);
+            while let Ok(Some(frame)) = tokio::time::timeout_at(at, stream.next()).await {
+                frames.push(frame);
+            }
+            match uplink {
+                Uplink::Audio(packet) => ws.send_audio(packet).await?,
+                Uplink::Frame(mut frame) => {
+                    if frame.get("session_id").is_some() {
+                        frame["session_id"] = Value::String(id.clone());
+                    }
+                    ws.send_text(frame.to_string()).await?;
+                }
+            }
+        }
+
+        let deadline = tokio::time::Instant::now() + TAIL_WAIT;
+        while let Ok(Some(frame)) = tokio::time::timeout_at(deadline, stream.next()).await {
+            frames.push(frame);
+        }
+
+        ws.close().await?;
+        Ok::<_, Box<dyn Error>>(frames)
+    })?
+    .iter()
+    .map(serde_json::to_value)
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let mut matched = recorded.len() == replayed.len();
+    for i in 0..recorded.len().max(replayed.len()) {
+        let a = recorded.get(i).map(kind).unwrap_or_default();
+        let b = replayed.get(i).map(kind).unwrap_or_default();
+        if a != b {
+            matched = false;
+            println!("- {}", a);
+            println!("+ {}", b);
+            continue;
+        }
+        println!("  {}", a);
+        // 文本随模型输出变化, 仅提示差异
+        let (a, b) = (&recorded[i]["text"], &replayed[i]["text"]);
+        if a != b {
+            println!("    ~ 文本: {} -> {}", a, b);
+        }
+    }
+
+    if matched {
+        info!("回放的控制帧序列与录制一致");
+        Ok(())
+    } else {
+        warn!("回放的控制帧序列与录制不一致");
+        std::process::exit(1);
+    }
+}
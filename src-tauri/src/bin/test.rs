@@ -20,7 +20,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
 
         state.write().await.stop().await?;
-        Ok::<(), String>(())
+        Ok::<(), app_lib::error::AppError>(())
     })
     .inspect_err(|_| error!("error"));
 
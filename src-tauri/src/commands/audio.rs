@@ -1,11 +1,13 @@
-use crate::state::AppState;
+use crate::{
+    audio::device_state::DeviceState, error::AppError, state::AppState, utils::queue::Dropped,
+};
 use tauri::State;
 use tracing::debug;
 #[tauri::command]
 pub async fn audio_start(
     state: State<'_, AppState>,
     webview: tauri::WebviewWindow,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     debug!("{}", webview.label());
 
     state
@@ -18,13 +20,45 @@ pub async fn audio_start(
 }
 
 #[tauri::command]
-pub async fn audio_stop(state: State<'_, AppState>) -> Result<(), String> {
+pub async fn audio_stop(state: State<'_, AppState>) -> Result<(), AppError> {
     state.audio_starte.read().await.stop().await?;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn abort() -> Result<(), String> {
+pub async fn get_device_state(state: State<'_, AppState>) -> Result<DeviceState, AppError> {
+    Ok(state.audio_starte.read().await.device_state().await)
+}
+
+/// 连接的往返时延 (ms)
+#[tauri::command]
+pub async fn get_rtt(state: State<'_, AppState>) -> Result<Option<u64>, AppError> {
+    let rtt = state.audio_starte.read().await.rtt().await;
+    Ok(rtt.map(|e| e.as_millis() as u64))
+}
+
+/// 队列满时丢弃的音频数据量
+#[tauri::command]
+pub async fn get_dropped(state: State<'_, AppState>) -> Result<Dropped, AppError> {
+    Ok(state.audio_starte.read().await.dropped().await)
+}
+
+#[tauri::command]
+pub async fn send_text_message(
+    state: State<'_, AppState>,
+    webview: tauri::WebviewWindow,
+    text: String,
+) -> Result<(), AppError> {
+    state
+        .audio_starte
+        .write()
+        .await
+        .send_text_message(text, Some(webview))
+        .await
+}
+
+#[tauri::command]
+pub async fn abort() -> Result<(), AppError> {
     // TODO: 终止对话
     unimplemented!()
 }
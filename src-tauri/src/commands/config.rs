@@ -0,0 +1,126 @@
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::utils::{
+    config::{Config, profile::ProfileCfg, validate::FieldError},
+    device::{DeviceType, list_devices},
+    log::set_level,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tauri::State;
+
+#[derive(Serialize)]
+pub struct AudioDevices {
+    input: Vec<String>,
+    output: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn get_config() -> Result<Config, AppError> {
+    Ok(Config::get_instance().redacted())
+}
+
+#[derive(Serialize)]
+pub struct Profiles {
+    active: Option<String>,
+    profiles: BTreeMap<String, ProfileCfg>,
+}
+
+impl From<&Config> for Profiles {
+    fn from(config: &Config) -> Self {
+        Self {
+            active: config.profile.clone(),
+            profiles: config
+                .profiles
+                .iter()
+                .map(|(name, profile)| (name.clone(), profile.redacted()))
+                .collect(),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn update_config(
+    state: State<'_, AppState>,
+    mut config: Config,
+) -> Result<Config, AppError> {
+    config.restore_secrets(&Config::get_instance());
+    Ok(apply(&state, config).await?.redacted())
+}
+
+/// 保存配置并使其生效, 服务器或音频参数变化时重新开始对话
+async fn apply(state: &AppState, config: Config) -> Result<Arc<Config>, AppError> {
+    let old = Config::update(config)?;
+    let new = Config::get_instance();
+
+    if old.logger.level != new.logger.level {
+        set_level(new.logger.clone().into())?;
+    }
+    state
+        .audio_starte
+        .write()
+        .await
+        .apply_config(&old, &new)
+        .await?;
+
+    Ok(new)
+}
+
+/// 校验给定配置, 未给出时校验当前配置 (含配置文件加载错误)
+#[tauri::command]
+pub async fn validate_config(config: Option<Config>) -> Result<Vec<FieldError>, AppError> {
+    Ok(match config {
+        Some(config) => config.diagnostics(),
+        None => Config::get_instance().diagnostics(),
+    })
+}
+
+#[tauri::command]
+pub async fn list_profiles() -> Result<Profiles, AppError> {
+    Ok(Config::get_instance().as_ref().into())
+}
+
+#[tauri::command]
+pub async fn add_profile(
+    state: State<'_, AppState>,
+    name: String,
+    mut profile: ProfileCfg,
+) -> Result<Profiles, AppError> {
+    let current = Config::get_instance();
+    if let Some(e) = current.profiles.get(name.trim()) {
+        profile.restore_secrets(e);
+    }
+    let config = current.add_profile(&name, profile)?;
+    Ok(apply(&state, config).await?.as_ref().into())
+}
+
+#[tauri::command]
+pub async fn remove_profile(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<Profiles, AppError> {
+    let config = Config::get_instance().remove_profile(&name)?;
+    Ok(apply(&state, config).await?.as_ref().into())
+}
+
+/// 切换服务器配置, 进行中的对话会被结束并以新配置重新连接
+#[tauri::command]
+pub async fn activate_profile(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<Profiles, AppError> {
+    let config = Config::get_instance().activate_profile(&name)?;
+    Ok(apply(&state, config).await?.as_ref().into())
+}
+
+#[tauri::command]
+pub async fn list_audio_devices() -> Result<AudioDevices, AppError> {
+    let list = |t| {
+        list_devices(t).map_err(|e| AppError::DeviceUnavailable(format!("查询设备失败: {}", e)))
+    };
+    Ok(AudioDevices {
+        input: list(DeviceType::Input)?,
+        output: list(DeviceType::Output)?,
+    })
+}
@@ -0,0 +1,40 @@
+use crate::error::AppError;
+use crate::utils::history::{History, HistoryEntry, SessionSummary};
+use tauri::State;
+
+#[tauri::command]
+pub async fn history_list(history: State<'_, History>) -> Result<Vec<SessionSummary>, AppError> {
+    history
+        .list()
+        .map_err(|e| AppError::History(format!("读取历史会话失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn history_transcript(
+    history: State<'_, History>,
+    session_id: String,
+) -> Result<Vec<HistoryEntry>, AppError> {
+    history
+        .transcript(&session_id)
+        .map_err(|e| AppError::History(format!("读取会话记录失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn history_search(
+    history: State<'_, History>,
+    query: String,
+) -> Result<Vec<HistoryEntry>, AppError> {
+    history
+        .search(&query)
+        .map_err(|e| AppError::History(format!("搜索历史记录失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn history_delete(
+    history: State<'_, History>,
+    session_id: String,
+) -> Result<(), AppError> {
+    history
+        .delete(&session_id)
+        .map_err(|e| AppError::History(format!("删除会话记录失败: {}", e)))
+}
@@ -1,6 +1,9 @@
+use crate::error::AppError;
 use tauri::Manager;
 
 pub mod audio;
+pub mod config;
+pub mod history;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -9,22 +12,22 @@ pub async fn greet(name: String) -> String {
 }
 
 #[tauri::command]
-pub async fn open_settings_window(app_handle: tauri::AppHandle) -> Result<(), String> {
+pub async fn open_settings_window(app_handle: tauri::AppHandle) -> Result<(), AppError> {
     let settings_window = app_handle
         .get_webview_window("settings")
-        .ok_or("未查询到设置窗口")?;
+        .ok_or(AppError::Window("未查询到设置窗口".to_string()))?;
 
     settings_window
         .show()
-        .map_err(|e| format!("设置窗口显示失败: {}", e))?;
+        .map_err(|e| AppError::Window(format!("设置窗口显示失败: {}", e)))?;
 
     if settings_window
         .is_visible()
-        .map_err(|e| format!("设置窗口查询失败: {}", e))?
+        .map_err(|e| AppError::Window(format!("设置窗口查询失败: {}", e)))?
     {
         settings_window
             .set_focus()
-            .map_err(|e| format!("设置窗口显示失败: {}", e))?;
+            .map_err(|e| AppError::Window(format!("设置窗口显示失败: {}", e)))?;
     }
 
     Ok(())
@@ -0,0 +1,111 @@
+//! 应用错误类型
+//!
+//! 命令返回的错误序列化为 `{ "code": "...", "message": "..." }`,
+//! 前端依据 `code` 进行处理, `message` 为可直接展示的中文提示
+
+use crate::audio::device_state::StateError;
+use crate::utils::config::validate::FieldError;
+use serde::Serialize;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum AppError {
+    #[error("WebSocket 连接失败: {0}")]
+    Connect(String),
+    #[error("等待服务器 hello 响应超时")]
+    HandshakeTimeout,
+    #[error("WebSocket 未连接")]
+    NotConnected,
+    #[error("消息发送失败: {0}")]
+    Send(String),
+    #[error("音频设备不可用: {0}")]
+    DeviceUnavailable(String),
+    #[error("音频编解码失败: {0}")]
+    Codec(String),
+    #[error("配置错误: {0}")]
+    Config(String),
+    /// 逐项给出的配置校验错误
+    #[error("配置无效: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidConfig(Vec<FieldError>),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error("窗口操作失败: {0}")]
+    Window(String),
+    #[error("历史记录操作失败: {0}")]
+    History(String),
+    #[error("参数错误: {0}")]
+    InvalidInput(String),
+    #[error("内部错误: {0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Connect(_) => "CONNECT",
+            AppError::HandshakeTimeout => "HANDSHAKE_TIMEOUT",
+            AppError::NotConnected => "NOT_CONNECTED",
+            AppError::Send(_) => "SEND",
+            AppError::DeviceUnavailable(_) => "DEVICE_UNAVAILABLE",
+            AppError::Codec(_) => "CODEC",
+            AppError::Config(_) => "CONFIG",
+            AppError::InvalidConfig(_) => "INVALID_CONFIG",
+            AppError::State(StateError::InvalidTransition { .. }) => "INVALID_STATE",
+            AppError::State(StateError::AlreadyActive(_)) => "ALREADY_ACTIVE",
+            AppError::State(StateError::NotActive) => "NOT_ACTIVE",
+            AppError::Window(_) => "WINDOW",
+            AppError::History(_) => "HISTORY",
+            AppError::InvalidInput(_) => "INVALID_INPUT",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            code: &'a str,
+            message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            fields: Option<&'a [FieldError]>,
+        }
+        Repr {
+            code: self.code(),
+            message: self.to_string(),
+            fields: match self {
+                AppError::InvalidConfig(errors) => Some(errors.as_slice()),
+                _ => None,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<opus::Error> for AppError {
+    fn from(e: opus::Error) -> Self {
+        AppError::Codec(e.to_string())
+    }
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+#[test]
+fn f() {
+    let json = serde_json::to_value(AppError::HandshakeTimeout).unwrap();
+    assert_eq!(json["code"], "HANDSHAKE_TIMEOUT");
+    assert_eq!(json["message"], "等待服务器 hello 响应超时");
+
+    let json = serde_json::to_value(AppError::from(StateError::NotActive)).unwrap();
+    assert_eq!(json["code"], "NOT_ACTIVE");
+    assert_eq!(json["message"], "对话未开始");
+    assert!(json.get("fields").is_none());
+
+    let json = serde_json::to_value(AppError::InvalidConfig(vec![FieldError::new(
+        "opus.channels",
+        "不支持的声道数 3",
+    )]))
+    .unwrap();
+    assert_eq!(json["code"], "INVALID_CONFIG");
+    assert_eq!(json["message"], "配置无效: opus.channels: 不支持的声道数 3");
+    assert_eq!(json["fields"][0]["field"], "opus.channels");
+}
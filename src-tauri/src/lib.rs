@@ -1,39 +1,75 @@
 use anyhow::anyhow;
 use commands::{
-    audio::{audio_start, audio_stop},
-    greet, open_settings_window,
+    audio::{audio_start, audio_stop, get_device_state, get_dropped, get_rtt, send_text_message},
+    config::{
+        activate_profile, add_profile, get_config, list_audio_devices, list_profiles,
+        remove_profile, update_config, validate_config,
+    },
+    greet,
+    history::{history_delete, history_list, history_search, history_transcript},
+    open_settings_window,
 };
 use std::ops::Not;
 use tauri::Manager;
 use tracing::error;
 pub mod audio;
 pub mod commands;
+pub mod error;
 pub mod state;
 pub mod types;
 pub mod utils;
 #[cfg(feature = "enable_window_event_log")]
 use tracing::debug;
 use types::SharedRwLock;
+use utils::config::paths;
+use utils::history::History;
+use utils::log::init_logger;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .setup(setup)
-        .manage(state::AppState::new())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             greet,
             audio_start,
             audio_stop,
-            open_settings_window
+            send_text_message,
+            get_device_state,
+            get_rtt,
+            get_dropped,
+            get_config,
+            update_config,
+            validate_config,
+            list_profiles,
+            add_profile,
+            remove_profile,
+            activate_profile,
+            list_audio_devices,
+            open_settings_window,
+            history_list,
+            history_transcript,
+            history_search,
+            history_delete
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
 fn setup(app: &mut tauri::App) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    // 配置与日志依赖应用目录, 需在读取配置前设置
+    paths::init(
+        app.path().app_config_dir()?,
+        app.path().app_data_dir()?,
+        app.path().app_log_dir()?,
+    );
+    init_logger();
+
     let exit_flag = SharedRwLock::new(false.into());
 
+    app.manage(state::AppState::new());
+    app.manage(History::new(paths::data_dir().join("history")));
+
     let main_window = app
         .get_webview_window("main")
         .ok_or(anyhow!("未查询到主窗口"))?;
@@ -5,6 +5,12 @@ pub struct AppState {
     pub audio_starte: AudioState,
 }
 
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppState {
     pub fn new() -> Self {
         tauri::async_runtime::block_on(Self::new_async())
@@ -0,0 +1,371 @@
+//! 配置分层: 默认值 < 配置文件 < 环境变量 < 命令行参数
+//!
+//! - 环境变量: `TAPP_<PATH>`, 如 `TAPP_WEBSOCKET_URL`, `TAPP_WEBSOCKET_TLS_CA_FILE`, `TAPP_PROFILE`
+//! - 命令行参数: `--<path> <value>` 或 `--<path>=<value>`, 如 `--websocket.proxy.url`, `--profile`
+//! - 配置文件位置: `--config <path>` 或环境变量 `TAPP_CONFIG`
+//!
+//! 保存配置时只将修改的配置项 ([`diff`]) 写回配置文件 ([`apply`]), 环境变量与命令行参数不会写入文件
+
+use std::ops::Not;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+pub const ENV_PREFIX: &str = "TAPP_";
+pub const ENV_CONFIG: &str = "TAPP_CONFIG";
+/// 类型表中表示任意名称的配置节, 如 `profiles.<name>`
+pub const ANY: &str = "*";
+
+/// 将 `over` 递归合并到 `base`
+pub fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// 配置项的修改, 值为空表示删除
+pub type Change = (Vec<String>, Option<Value>);
+
+/// 比较两份配置, 返回叶子节点上的修改
+pub fn diff(old: &Table, new: &Table) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_into(&mut Vec::new(), old, new, &mut changes);
+    changes
+}
+
+fn diff_into(path: &mut Vec<String>, old: &Table, new: &Table, changes: &mut Vec<Change>) {
+    for (key, value) in new {
+        path.push(key.clone());
+        match (old.get(key), value) {
+            (Some(Value::Table(old)), Value::Table(new)) => diff_into(path, old, new, changes),
+            (Some(old), new) if old == new => {}
+            (_, new) => changes.push((path.clone(), Some(new.clone()))),
+        }
+        path.pop();
+    }
+    for key in old.keys().filter(|e| new.contains_key(*e).not()) {
+        let mut path = path.clone();
+        path.push(key.clone());
+        changes.push((path, None));
+    }
+}
+
+/// 将修改应用到 `table`, 按需创建配置节
+pub fn apply(table: &mut Table, changes: &[Change]) {
+    for (path, value) in changes {
+        let Some((key, sections)) = path.split_last() else {
+            continue;
+        };
+        match value {
+            Some(value) => {
+                let mut table = &mut *table;
+                for section in sections {
+                    let entry = table
+                        .entry(section.clone())
+                        .or_insert(Value::Table(Table::new()));
+                    if entry.is_table().not() {
+                        *entry = Value::Table(Table::new());
+                    }
+                    table = entry.as_table_mut().expect("配置节应为表");
+                }
+                table.insert(key.clone(), value.clone());
+            }
+            None => {
+                let mut table = Some(&mut *table);
+                for section in sections {
+                    table = table.and_then(|e| e.get_mut(section)?.as_table_mut());
+                }
+                if let Some(table) = table {
+                    table.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// 类型表中的子项, 名称不固定的配置节取 [`ANY`]
+fn child<'a>(schema: &'a Table, key: &str) -> Option<&'a Value> {
+    schema.get(key).or_else(|| schema.get(ANY))
+}
+
+/// 按点分路径设置配置项, 字符串按 `schema` 中对应项的类型解析
+///
+/// `schema` 为列出全部配置项的类型表; 未知的配置项, 以及不存在的 [`ANY`] 配置节
+/// (如未定义的服务器配置) 返回错误
+pub fn set(table: &mut Table, schema: &Table, path: &str, raw: &str) -> Result<(), String> {
+    let unknown = || format!("未知的配置项: {}", path);
+    let keys = path.split('.').collect::<Vec<_>>();
+    if keys.contains(&ANY) {
+        return Err(unknown());
+    }
+    let (key, sections) = keys.split_last().ok_or_else(unknown)?;
+
+    // 先确认路径有效, 避免创建残缺的配置节
+    let mut kind = schema;
+    let mut existing = Some(&*table);
+    for section in sections {
+        let fixed = kind.contains_key(*section);
+        kind = child(kind, section)
+            .and_then(Value::as_table)
+            .ok_or_else(unknown)?;
+        existing = existing.and_then(|e| e.get(*section)?.as_table());
+        // 名称不固定的配置节需已存在
+        if fixed.not() && existing.is_none() {
+            return Err(format!("配置节 {} 不存在: {}", section, path));
+        }
+    }
+    let invalid = |e: &dyn std::fmt::Display| format!("配置项 {} 的值无效: {}: {}", path, raw, e);
+    let value = match child(kind, key).ok_or_else(unknown)? {
+        Value::Integer(_) => Value::Integer(raw.parse().map_err(|e| invalid(&e))?),
+        Value::Float(_) => Value::Float(raw.parse().map_err(|e| invalid(&e))?),
+        Value::Boolean(_) => Value::Boolean(raw.parse().map_err(|e| invalid(&e))?),
+        Value::Array(_) => parse_array(raw).map_err(|e| invalid(&e))?,
+        Value::Table(_) => return Err(format!("配置项 {} 是配置节, 应设置其中的配置项", path)),
+        _ => Value::String(raw.to_string()),
+    };
+
+    let mut table = &mut *table;
+    for section in sections {
+        table = table
+            .entry(section.to_string())
+            .or_insert(Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or(format!("配置项 {} 不是配置节", section))?;
+    }
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+/// 解析数组, 可为 TOML 数组或以逗号分隔的字符串
+fn parse_array(raw: &str) -> Result<Value, String> {
+    if raw.trim_start().starts_with('[') {
+        return toml::from_str::<Table>(&format!("value = {}", raw))
+            .map_err(|e| e.to_string())?
+            .remove("value")
+            .ok_or("缺少数组".to_string());
+    }
+    Ok(Value::Array(
+        raw.split(',')
+            .map(str::trim)
+            .filter(|e| e.is_empty().not())
+            .map(|e| Value::String(e.to_string()))
+            .collect(),
+    ))
+}
+
+/// 按 `schema` 将 `_` 分隔的单词还原为配置项路径
+fn env_path(schema: &Table, words: &[&str]) -> Option<Vec<String>> {
+    (1..=words.len()).find_map(|n| {
+        let key = words[..n].join("_");
+        let rest = &words[n..];
+        match (child(schema, &key)?, rest.is_empty()) {
+            (Value::Table(schema), false) => {
+                let mut path = env_path(schema, rest)?;
+                path.insert(0, key);
+                Some(path)
+            }
+            (Value::Table(_), true) | (_, false) => None,
+            (_, true) => Some(vec![key]),
+        }
+    })
+}
+
+/// 从环境变量中提取配置项, 无法对应到 `schema` 的变量保留原名, 由 [`set`] 报告
+pub fn env_overrides(
+    vars: impl Iterator<Item = (String, String)>,
+    schema: &Table,
+) -> Vec<(String, String)> {
+    vars.filter(|(k, _)| k != ENV_CONFIG)
+        .filter_map(|(k, v)| {
+            let name = k.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let words = name.split('_').collect::<Vec<_>>();
+            match env_path(schema, &words) {
+                Some(path) => Some((path.join("."), v)),
+                None => Some((k, v)),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub config: Option<PathBuf>,
+    pub overrides: Vec<(String, String)>,
+}
+
+/// 解析命令行参数, 忽略无关参数: 仅保留含 `.` 的路径与 `schema` 中的顶层配置项
+pub fn parse_args(args: impl Iterator<Item = String>, schema: &Table) -> CliArgs {
+    let mut cli = CliArgs::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            continue;
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name, None),
+        };
+        let top_level = schema.get(name).is_some_and(|e| e.is_table().not());
+        if name != "config" && name.contains('.').not() && top_level.not() {
+            continue;
+        }
+        let Some(value) = value.or_else(|| args.next_if(|e| e.starts_with("--").not())) else {
+            continue;
+        };
+        if name == "config" {
+            cli.config = Some(PathBuf::from(value));
+        } else {
+            cli.overrides.push((name.to_string(), value));
+        }
+    }
+    cli
+}
+
+#[test]
+fn f() {
+    let mut base: Table = toml::from_str(
+        r#"
+        [websocket]
+        url = "ws://a"
+        [opus]
+        frame_duration = 60
+        [recorder]
+        enabled = false
+        "#,
+    )
+    .unwrap();
+    merge(
+        &mut base,
+        toml::from_str("[websocket]\nurl = \"ws://b\"").unwrap(),
+    );
+    assert_eq!(base["websocket"]["url"].as_str(), Some("ws://b"));
+    assert_eq!(base["opus"]["frame_duration"].as_integer(), Some(60));
+
+    // 只写回修改的配置项, 文件中未出现的配置节按需创建
+    let mut new = base.clone();
+    new["opus"]["frame_duration"] = Value::Integer(20);
+    new["websocket"].as_table_mut().unwrap().remove("url");
+    new.insert("device".into(), toml::from_str("input = \"USB\"").unwrap());
+    let changes = diff(&base, &new);
+    assert_eq!(changes.len(), 3);
+    let mut file: Table = toml::from_str("[websocket]\nurl = \"ws://a\"").unwrap();
+    apply(&mut file, &changes);
+    assert_eq!(
+        file,
+        toml::from_str(
+            "websocket = {}\nopus = { frame_duration = 20 }\ndevice = { input = \"USB\" }"
+        )
+        .unwrap()
+    );
+
+    let schema: Table = toml::from_str(
+        r#"
+        profile = ""
+        [opus]
+        frame_duration = 0
+        bitrate = 0
+        [recorder]
+        enabled = false
+        [device]
+        input = ""
+        [websocket.tls]
+        ca_file = ""
+        [websocket.proxy]
+        no_proxy = [""]
+        [profiles."*".websocket]
+        url = ""
+        "#,
+    )
+    .unwrap();
+    let env = env_overrides(
+        [
+            ("TAPP_OPUS_FRAME_DURATION", "20"),
+            ("TAPP_WEBSOCKET_TLS_CA_FILE", "ca.pem"),
+            ("TAPP_PROFILE", "local"),
+            ("TAPP_OPUS_COLOR", "red"),
+            ("TAPP_CONFIG", "/tmp/a.toml"),
+            ("HOME", "/root"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string())),
+        &schema,
+    );
+    let paths = env.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            "opus.frame_duration",
+            "websocket.tls.ca_file",
+            "profile",
+            "TAPP_OPUS_COLOR"
+        ]
+    );
+    for (k, v) in &env[..3] {
+        set(&mut base, &schema, k, v).unwrap();
+    }
+    assert_eq!(base["opus"]["frame_duration"].as_integer(), Some(20));
+    assert_eq!(base["websocket"]["tls"]["ca_file"].as_str(), Some("ca.pem"));
+    assert_eq!(base["profile"].as_str(), Some("local"));
+    // 无法对应的环境变量与未知的配置项报告错误, 不写入
+    assert!(set(&mut base, &schema, "TAPP_OPUS_COLOR", "red").is_err());
+    assert!(set(&mut base, &schema, "opus.color", "red").is_err());
+    assert!(set(&mut base, &schema, "recorder.enabled", "yes").is_err());
+    assert!(set(&mut base, &schema, "websocket.tls", "x").is_err());
+    // 默认配置中注释掉的配置项同样按类型解析
+    set(&mut base, &schema, "opus.bitrate", "24000").unwrap();
+    assert_eq!(base["opus"]["bitrate"].as_integer(), Some(24000));
+    assert!(set(&mut base, &schema, "opus.bitrate", "high").is_err());
+    set(
+        &mut base,
+        &schema,
+        "websocket.proxy.no_proxy",
+        "localhost, .lan",
+    )
+    .unwrap();
+    assert_eq!(
+        base["websocket"]["proxy"]["no_proxy"],
+        Value::Array(vec!["localhost".into(), ".lan".into()])
+    );
+    set(&mut base, &schema, "device.input", "USB").unwrap();
+    assert_eq!(base["device"]["input"].as_str(), Some("USB"));
+    // 只能修改已存在的服务器配置
+    assert!(set(&mut base, &schema, "profiles.local.websocket.url", "ws://d").is_err());
+    assert!(set(&mut base, &schema, "profiles.*.websocket.url", "ws://d").is_err());
+    assert!(base.get("profiles").is_none());
+    base.insert("profiles".into(), toml::from_str("local = {}").unwrap());
+    set(&mut base, &schema, "profiles.local.websocket.url", "ws://d").unwrap();
+    assert_eq!(
+        base["profiles"]["local"]["websocket"]["url"].as_str(),
+        Some("ws://d")
+    );
+
+    let cli = parse_args(
+        [
+            "--config",
+            "a.toml",
+            "--websocket.url=ws://c",
+            "--opus.sample_rate",
+            "24000",
+            "--profile",
+            "local",
+            "--verbose",
+            "1",
+        ]
+        .into_iter()
+        .map(String::from),
+        &schema,
+    );
+    assert_eq!(cli.config, Some(PathBuf::from("a.toml")));
+    assert_eq!(
+        cli.overrides,
+        [
+            ("websocket.url".to_string(), "ws://c".to_string()),
+            ("opus.sample_rate".to_string(), "24000".to_string()),
+            ("profile".to_string(), "local".to_string())
+        ]
+    );
+}
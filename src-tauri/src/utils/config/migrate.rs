@@ -0,0 +1,190 @@
+//! 配置文件版本迁移, 未标注 `version` 的配置视为版本 0
+
+use super::validate::FieldError;
+use std::ops::Not;
+use toml::{Table, Value};
+
+pub const CURRENT_VERSION: i64 = 2;
+
+type Migration = fn(&mut Table);
+
+/// `MIGRATIONS[n]` 将版本 n 升级到 n + 1
+const MIGRATIONS: &[Migration] = &[v0_to_v1, v1_to_v2];
+
+/// 版本 0 的相对路径以工作目录为基准, 版本 1 改为应用目录, 旧的默认值替换为新的默认值
+fn v0_to_v1(table: &mut Table) {
+    let mut replace = |section: &str, key: &str, old: &str, new: &str| {
+        if let Some(Value::Table(section)) = table.get_mut(section)
+            && section.get(key).and_then(Value::as_str) == Some(old)
+        {
+            section.insert(key.to_string(), Value::String(new.to_string()));
+        }
+    };
+    replace("logger", "file_path", "../.log", "t-app.log");
+    replace("recorder", "dir", "../.record", "record");
+}
+
+/// 版本 1 以 `websocket.frame_size` 表示帧长, 版本 2 改为 `opus.frame_duration` (ms)
+///
+/// 无法换算为支持的帧时长时保留旧字段, 加载时报告, 见 [`unmigrated`]
+fn v1_to_v2(table: &mut Table) {
+    fn convert(server: &mut Table) {
+        let Some(frame_size) = server
+            .get("websocket")
+            .and_then(|e| e.get("frame_size"))
+            .and_then(Value::as_integer)
+        else {
+            return;
+        };
+        let sample_rate = server
+            .get("opus")
+            .and_then(|e| e.get("sample_rate"))
+            .and_then(Value::as_integer)
+            .filter(|e| *e > 0)
+            .unwrap_or(16000);
+        let duration = frame_size * 1000 / sample_rate;
+        if frame_size * 1000 % sample_rate != 0 || [10, 20, 40, 60].contains(&duration).not() {
+            return;
+        }
+        if let Some(Value::Table(ws)) = server.get_mut("websocket") {
+            ws.remove("frame_size");
+        }
+        if let Value::Table(opus) = server.entry("opus").or_insert(Value::Table(Table::new())) {
+            opus.insert("frame_duration".to_string(), Value::Integer(duration));
+        }
+    }
+
+    convert(table);
+    if let Some(Value::Table(profiles)) = table.get_mut("profiles") {
+        for (_, profile) in profiles.iter_mut() {
+            if let Value::Table(profile) = profile {
+                convert(profile);
+            }
+        }
+    }
+}
+
+/// 迁移时无法换算而保留的旧字段, 该字段不再生效
+pub fn unmigrated(table: &Table) -> Vec<FieldError> {
+    let frame_size = |prefix: &str, server: &Table| {
+        server
+            .get("websocket")
+            .and_then(|e| e.get("frame_size"))
+            .map(|e| {
+                FieldError::new(
+                    &format!("{}websocket.frame_size", prefix),
+                    format!(
+                        "旧版帧长 {} 无法换算为支持的帧时长, 已忽略, 请设置 {}opus.frame_duration 后删除该项",
+                        e, prefix
+                    ),
+                )
+            })
+    };
+    let mut errors = Vec::from_iter(frame_size("", table));
+    if let Some(Value::Table(profiles)) = table.get("profiles") {
+        for (name, profile) in profiles {
+            if let Value::Table(profile) = profile {
+                errors.extend(frame_size(&format!("profiles.{}.", name), profile));
+            }
+        }
+    }
+    errors
+}
+
+/// 将配置升级到当前版本, 返回是否发生了迁移
+pub fn migrate(table: &mut Table) -> Result<bool, String> {
+    let version = match table.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_integer()
+            .filter(|e| *e >= 0)
+            .ok_or(format!("配置版本无效: {}", v))?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "配置版本 {} 高于当前支持的版本 {}",
+            version, CURRENT_VERSION
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(table);
+    }
+    table.insert("version".to_string(), Value::Integer(CURRENT_VERSION));
+    Ok(version < CURRENT_VERSION)
+}
+
+#[test]
+fn f() {
+    assert_eq!(MIGRATIONS.len() as i64, CURRENT_VERSION);
+
+    let mut table: Table = toml::from_str(
+        r#"
+        [logger]
+        file_path = "../.log"
+        [recorder]
+        dir = "/data/record"
+        "#,
+    )
+    .unwrap();
+    assert!(migrate(&mut table).unwrap());
+    assert_eq!(table["version"].as_integer(), Some(CURRENT_VERSION));
+    assert_eq!(table["logger"]["file_path"].as_str(), Some("t-app.log"));
+    assert_eq!(table["recorder"]["dir"].as_str(), Some("/data/record"));
+
+    let mut table: Table = toml::from_str(
+        r#"
+        version = 1
+        [websocket]
+        url = "ws://a"
+        frame_size = 480
+        [opus]
+        sample_rate = 24000
+        [profiles.local.websocket]
+        url = "ws://b"
+        frame_size = 960
+        [profiles.local.opus]
+        sample_rate = 16000
+        "#,
+    )
+    .unwrap();
+    assert!(migrate(&mut table).unwrap());
+    assert_eq!(table["opus"]["frame_duration"].as_integer(), Some(20));
+    assert!(table["websocket"].get("frame_size").is_none());
+    let local = &table["profiles"]["local"];
+    assert_eq!(local["opus"]["frame_duration"].as_integer(), Some(60));
+    assert!(local["websocket"].get("frame_size").is_none());
+
+    assert!(unmigrated(&table).is_empty());
+    assert!(!migrate(&mut table).unwrap());
+
+    // 无法换算的帧长保留原值并报告
+    let mut table: Table = toml::from_str(
+        r#"
+        version = 1
+        [websocket]
+        url = "ws://a"
+        frame_size = 330
+        [profiles.local.websocket]
+        url = "ws://b"
+        frame_size = 1200
+        "#,
+    )
+    .unwrap();
+    assert!(migrate(&mut table).unwrap());
+    assert_eq!(table["websocket"]["frame_size"].as_integer(), Some(330));
+    assert!(table.get("opus").is_none());
+    let fields = unmigrated(&table)
+        .into_iter()
+        .map(|e| e.field)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        [
+            "websocket.frame_size",
+            "profiles.local.websocket.frame_size"
+        ]
+    );
+
+    table.insert("version".to_string(), Value::Integer(CURRENT_VERSION + 1));
+    assert!(migrate(&mut table).is_err());
+}
@@ -0,0 +1,609 @@
+pub mod layer;
+pub mod migrate;
+pub mod paths;
+pub mod profile;
+pub mod secret;
+pub mod validate;
+
+use super::device::{DeviceConfig, DeviceType, get_device_config};
+use super::frame::client::AudioParams;
+use crate::error::AppError;
+use profile::ProfileCfg;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, PoisonError, RwLock},
+};
+use toml::Table;
+use tracing::level_filters::LevelFilter;
+use validate::FieldError;
+
+const DEFAULT_CONFIG: &str = r#"
+version = 2
+# 当前使用的服务器配置, 见文末 [profiles]
+# profile = "local"
+
+[websocket]
+# 传输方式: "websocket", 或 "udp" (控制帧经 [mqtt], 音频经加密 UDP)
+transport = "websocket"
+url = "ws://10.243.197.206:8080"
+# 二进制协议版本: 1 为裸音频数据, 2 带时间戳, 3 为精简头部
+protocol_version = 1
+# 心跳间隔 (ms), 0 为不发送心跳
+ping_interval = 20000
+# 等待 pong 的超时 (ms), 超时视为连接已断开
+pong_timeout = 10000
+# 等待服务器 hello 回复的超时 (ms)
+hello_timeout = 5000
+# 超时后重发 hello 的次数, 均超时则连接失败
+hello_retries = 1
+# 握手时以 Authorization: Bearer <token> 发送
+# token = ""
+
+# wss:// 连接的 TLS 设置, 相对路径以应用配置目录为基准
+# [websocket.tls]
+# 额外信任的 CA 证书 (PEM), 用于自签名或私有 CA 的服务器
+# ca_file = "ca.pem"
+# 双向 TLS 的客户端证书与私钥 (PEM)
+# client_cert = "client.pem"
+# client_key = "client.key"
+# 服务器公钥固定: SPKI 的 SHA-256 摘要 (base64), 校验所用证书路径中任一公钥匹配即可
+# pins = ["sha256/..."]
+
+# 代理设置
+# [websocket.proxy]
+# 代理地址: http://[user:pass@]host:port 或 socks5://[user:pass@]host:port
+# 未设置时读取环境变量 HTTPS_PROXY (wss) / HTTP_PROXY (ws) / ALL_PROXY
+# url = ""
+# 为 false 时忽略代理相关的环境变量
+# use_env = true
+# 不经代理直连的主机, 匹配主机本身及其子域名, "*" 匹配全部, 与 NO_PROXY 合并
+# no_proxy = ["localhost"]
+
+[opus]
+# 音频格式: "opus" 或 "pcm16" (未压缩, 用于局域网部署与调试)
+format = "opus"
+sample_rate = 16000
+# 1 或 2
+channels = 1
+# 帧时长 (ms), 10/20/40/60
+frame_duration = 60
+# 以下编码参数在每次对话开始时生效
+# 目标码率 (bit/s), 未设置时由编码器自动选择
+# bitrate = 24000
+vbr = true
+# 带内前向纠错, 配合 packet_loss 使用
+fec = false
+# 预期丢包率 (%), 0-100
+packet_loss = 0
+# 编码复杂度, 0-10, 越高音质越好、CPU 占用越高
+complexity = 10
+# 静音时不连续发送 (DTX), 仅发送少量舒适噪声包
+dtx = false
+
+[mqtt]
+# websocket.transport = "udp" 时使用, 地址格式 host:port, 端口缺省为 1883
+endpoint = ""
+client_id = ""
+username = ""
+password = ""
+publish_topic = ""
+subscribe_topic = ""
+
+[logger]
+level = "debug"
+# 相对路径以应用日志目录为基准
+file_path = "t-app.log"
+# mode = "append" or "overwrite"
+# 默认值为 "append"
+mode = "overwrite"
+
+[device]
+# 输入/输出设备名称, 未设置时使用系统默认设备
+# input = ""
+# output = ""
+
+[recorder]
+# 录制会话的上下行音频与控制帧, 用于调试与回放
+enabled = false
+# 相对路径以应用数据目录为基准
+dir = "record"
+
+# 服务器配置, 切换时覆盖 [websocket]、[mqtt] 与 [opus]
+# [profiles.local.websocket]
+# url = "ws://127.0.0.1:8000"
+# [profiles.local.opus]
+# sample_rate = 16000
+# channels = 1
+# frame_duration = 60
+"#;
+
+const CONFIG_FILE: &str = "config.toml";
+/// 旧版本放在工作目录下的配置文件, 首次启动时导入
+const LEGACY_CONFIG_FILE: &str = ".Config.toml";
+
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpusCfg {
+    /// 音频格式, 见 [`crate::audio::codec::FORMATS`]
+    pub format: String,
+    pub sample_rate: usize,
+    pub channels: usize,
+    /// 帧时长, 毫秒
+    pub frame_duration: u32,
+    /// 目标码率 (bit/s), 为空时由编码器自动选择
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<i32>,
+    pub vbr: bool,
+    /// 带内前向纠错
+    pub fec: bool,
+    /// 预期丢包率 (%)
+    pub packet_loss: i32,
+    /// 编码复杂度, 0-10
+    pub complexity: i32,
+    /// 静音时不连续发送
+    pub dtx: bool,
+}
+
+impl Default for OpusCfg {
+    fn default() -> Self {
+        Self {
+            format: "opus".to_string(),
+            sample_rate: 16000,
+            channels: 1,
+            frame_duration: 60,
+            bitrate: None,
+            vbr: true,
+            fec: false,
+            packet_loss: 0,
+            complexity: 10,
+            dtx: false,
+        }
+    }
+}
+
+impl OpusCfg {
+    /// 每帧每声道的采样数
+    pub fn frame_size(&self) -> usize {
+        self.sample_rate * self.frame_duration as usize / 1000
+    }
+
+    /// `hello` 消息中的音频参数
+    pub fn audio_params(&self) -> AudioParams {
+        AudioParams {
+            format: self.format.clone(),
+            sample_rate: self.sample_rate as u32,
+            channels: self.channels as u32,
+            frame_duration: self.frame_duration,
+        }
+    }
+}
+
+impl From<OpusCfg> for opus::Channels {
+    fn from(cfg: OpusCfg) -> Self {
+        match cfg.channels {
+            1 => opus::Channels::Mono,
+            2 => opus::Channels::Stereo,
+            _ => opus::Channels::Stereo,
+        }
+    }
+}
+
+/// 敏感字段的 Debug 输出已脱敏, 见 [`secret`]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct WsCfg {
+    /// 传输方式, 见 [`crate::utils::transport::TRANSPORTS`]
+    #[serde(default = "WsCfg::default_transport")]
+    pub transport: String,
+    pub url: String,
+    /// 二进制协议版本, 见 [`crate::utils::frame::binary`]
+    #[serde(default = "WsCfg::default_protocol_version")]
+    pub protocol_version: u32,
+    /// 心跳间隔, 毫秒, 为 0 时不发送心跳
+    #[serde(default = "WsCfg::default_ping_interval")]
+    pub ping_interval: u64,
+    /// 等待 pong 的超时, 毫秒
+    #[serde(default = "WsCfg::default_pong_timeout")]
+    pub pong_timeout: u64,
+    /// 等待服务器 `hello` 的超时, 毫秒, 同样用于 udp 传输
+    #[serde(default = "WsCfg::default_hello_timeout")]
+    pub hello_timeout: u64,
+    /// 超时后重发 `hello` 的次数
+    #[serde(default = "WsCfg::default_hello_retries")]
+    pub hello_retries: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub tls: TlsCfg,
+    #[serde(default)]
+    pub proxy: ProxyCfg,
+}
+
+impl WsCfg {
+    fn default_transport() -> String {
+        crate::utils::transport::WEBSOCKET.to_string()
+    }
+
+    fn default_protocol_version() -> u32 {
+        1
+    }
+
+    fn default_ping_interval() -> u64 {
+        20000
+    }
+
+    fn default_pong_timeout() -> u64 {
+        10000
+    }
+
+    fn default_hello_timeout() -> u64 {
+        5000
+    }
+
+    fn default_hello_retries() -> u32 {
+        1
+    }
+}
+
+/// `wss://` 连接的 TLS 设置, 见 [`crate::utils::tls`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsCfg {
+    /// 额外信任的 CA 证书 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<PathBuf>,
+    /// 双向 TLS 的客户端证书 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    /// 客户端证书的私钥 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+    /// 服务器公钥固定值, 见 [`crate::utils::tls::parse_pin`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pins: Vec<String>,
+}
+
+/// 代理设置, 见 [`crate::utils::proxy`]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyCfg {
+    /// 代理地址, 为空时读取环境变量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// 是否读取 `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY`/`NO_PROXY`
+    pub use_env: bool,
+    /// 不经代理直连的主机
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+}
+
+impl Default for ProxyCfg {
+    fn default() -> Self {
+        Self {
+            url: None,
+            use_env: true,
+            no_proxy: vec![],
+        }
+    }
+}
+
+/// MQTT 控制通道, `websocket.transport = "udp"` 时使用
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttCfg {
+    /// 服务器地址, `host:port`, 端口缺省为 1883
+    pub endpoint: String,
+    pub client_id: String,
+    pub username: String,
+    pub password: String,
+    /// 上行控制帧的发布主题
+    pub publish_topic: String,
+    /// 下行控制帧的订阅主题, 为空时不订阅
+    pub subscribe_topic: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogCfg {
+    pub level: String,
+    pub file_path: PathBuf,
+    pub mode: String,
+}
+
+impl From<LogCfg> for LevelFilter {
+    fn from(cfg: LogCfg) -> Self {
+        match cfg.level.to_lowercase().as_str() {
+            "trace" => tracing::Level::TRACE,
+            "debug" => tracing::Level::DEBUG,
+            "info" => tracing::Level::INFO,
+            "warn" => tracing::Level::WARN,
+            "error" => tracing::Level::ERROR,
+            _ => {
+                println!("日志级别设置错误, 使用默认级别: info");
+                tracing::Level::INFO
+            }
+        }
+        .into()
+    }
+}
+
+impl TryFrom<LogCfg> for std::fs::File {
+    type Error = AppError;
+
+    fn try_from(cfg: LogCfg) -> Result<Self, Self::Error> {
+        let mut options = std::fs::OpenOptions::new();
+
+        match cfg.mode.to_lowercase().as_str() {
+            "append" => options.append(true),
+            "overwrite" => options.write(true).truncate(true),
+            _ => {
+                println!("日志模式设置错误, 使用默认模式: append");
+                options.append(true)
+            }
+        }
+        .create(true)
+        .clone()
+        .open({
+            let path = paths::resolve(&paths::log_dir(), &cfg.file_path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| AppError::Config(format!("日志目录创建失败: {}", e)))?;
+            }
+            path
+        })
+        .map_err(|e| {
+            AppError::Config(format!(
+                "日志文件打开失败: {}: {}",
+                cfg.file_path.display(),
+                e
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecorderCfg {
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for RecorderCfg {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("record"),
+        }
+    }
+}
+
+/// 音频设备名称, 为空时使用系统默认设备
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceCfg {
+    pub input: Option<String>,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// 配置文件版本, 见 [`migrate`]
+    #[serde(default)]
+    pub version: i64,
+    /// 当前使用的服务器配置名称
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// WebSocket URL
+    pub websocket: WsCfg,
+    #[serde(default)]
+    pub mqtt: MqttCfg,
+    pub opus: OpusCfg,
+    pub logger: LogCfg,
+    #[serde(default)]
+    pub device: DeviceCfg,
+    #[serde(default)]
+    pub recorder: RecorderCfg,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileCfg>,
+    #[serde(skip)]
+    pub input_device: DeviceConfig,
+    #[serde(skip)]
+    pub output_device: DeviceConfig,
+    /// 配置文件路径
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// 加载配置文件时的错误, 配置文件无法解析时使用默认配置
+    #[serde(skip)]
+    pub load_errors: Vec<FieldError>,
+}
+
+impl Config {
+    fn new() -> Self {
+        let cli = layer::parse_args(std::env::args().skip(1), &Self::schema());
+        let path = cli
+            .config
+            .clone()
+            .or_else(|| std::env::var_os(layer::ENV_CONFIG).map(PathBuf::from))
+            .unwrap_or_else(|| paths::config_dir().join(CONFIG_FILE));
+
+        let mut config = Self::load(&path, &cli.overrides).unwrap_or_else(|e| {
+            println!("{}, 使用默认配置", e);
+            let mut config: Config = toml::from_str(DEFAULT_CONFIG).expect("默认配置解析失败");
+            config.path = path;
+            config.load_errors.push(FieldError::new("", e.to_string()));
+            config
+        });
+        config.resolve_devices();
+
+        println!("配置: \n{:#?}", config);
+        for e in config.diagnostics() {
+            println!("配置错误: {}", e);
+        }
+        config
+    }
+
+    /// 按 默认值 < 配置文件 < 环境变量 < 命令行参数 的顺序合并配置
+    fn load(path: &Path, cli_overrides: &[(String, String)]) -> Result<Self, AppError> {
+        let mut table: Table = toml::from_str(DEFAULT_CONFIG).expect("默认配置解析失败");
+        layer::merge(&mut table, Self::read_file(path)?);
+
+        // 无效的覆盖项不影响其他配置, 跳过并报告
+        let schema = Self::schema();
+        let mut load_errors = vec![];
+        for (key, value) in layer::env_overrides(std::env::vars(), &schema)
+            .iter()
+            .chain(cli_overrides)
+        {
+            if let Err(e) = layer::set(&mut table, &schema, key, value) {
+                load_errors.push(FieldError::new(key, e));
+            }
+        }
+
+        load_errors.extend(migrate::unmigrated(&table));
+        let mut config: Config = table
+            .try_into()
+            .map_err(|e| AppError::Config(format!("配置解析失败: {}", e)))?;
+        config.path = path.to_path_buf();
+        config.load_errors = load_errors;
+        Ok(config)
+    }
+
+    /// 列出全部配置项的类型表, 可选项取示例值, 用于解析环境变量与命令行参数
+    fn schema() -> Table {
+        let mut config: Config = toml::from_str(DEFAULT_CONFIG).expect("默认配置解析失败");
+        config.profile = Some(String::new());
+        config.opus.bitrate = Some(0);
+        config.websocket.token = Some(String::new());
+        config.websocket.tls = TlsCfg {
+            ca_file: Some(PathBuf::new()),
+            client_cert: Some(PathBuf::new()),
+            client_key: Some(PathBuf::new()),
+            pins: vec![String::new()],
+        };
+        config.websocket.proxy.url = Some(String::new());
+        config.websocket.proxy.no_proxy = vec![String::new()];
+        config.device.input = Some(String::new());
+        config.device.output = Some(String::new());
+        let profile = ProfileCfg {
+            websocket: config.websocket.clone(),
+            mqtt: config.mqtt.clone(),
+            opus: config.opus.clone(),
+        };
+        config.profiles.insert(layer::ANY.to_string(), profile);
+        config.to_table().expect("配置类型表生成失败")
+    }
+
+    /// 读取配置文件并升级到当前版本, 仅在首次创建或发生迁移时写入文件
+    fn read_file(path: &Path) -> Result<Table, AppError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => {
+                let text = std::fs::read_to_string(LEGACY_CONFIG_FILE)
+                    .inspect(|_| println!("导入旧配置文件: {}", LEGACY_CONFIG_FILE))
+                    .unwrap_or_else(|_| DEFAULT_CONFIG.to_string());
+                println!("{} 未发现配置文件, 创建配置文件", path.display());
+                Self::write_file(path, &text)?;
+                text
+            }
+        };
+
+        let mut table: Table = toml::from_str(&text).map_err(|e| {
+            AppError::Config(format!("配置文件解析失败: {}: {}", path.display(), e))
+        })?;
+        if migrate::migrate(&mut table).map_err(AppError::Config)? {
+            println!("配置文件已升级到版本 {}", migrate::CURRENT_VERSION);
+            std::fs::copy(path, path.with_extension("toml.bak"))
+                .map_err(|e| AppError::Config(format!("配置文件备份失败: {}", e)))?;
+            let text = toml::to_string_pretty(&table)
+                .map_err(|e| AppError::Config(format!("配置序列化失败: {}", e)))?;
+            Self::write_file(path, &text)?;
+        }
+        Ok(table)
+    }
+
+    fn to_table(&self) -> Result<Table, AppError> {
+        toml::Value::try_from(self)
+            .map_err(|e| AppError::Config(format!("配置序列化失败: {}", e)))?
+            .try_into()
+            .map_err(|e| AppError::Config(format!("配置序列化失败: {}", e)))
+    }
+
+    fn write_file(path: &Path, text: &str) -> Result<(), AppError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| AppError::Config(format!("配置目录创建失败: {}", e)))?;
+        }
+        std::fs::write(path, text).map_err(|e| AppError::Config(format!("配置文件写入失败: {}", e)))
+    }
+
+    /// 按设备名称查询设备参数, 设备不可用时保留空配置, 启动对话时再报告错误
+    fn resolve_devices(&mut self) {
+        self.input_device = get_device_config(DeviceType::Input, self.device.input.as_deref())
+            .inspect_err(|e| println!("获取输入设备配置失败: {}", e))
+            .map(Into::into)
+            .unwrap_or_default();
+        self.output_device = get_device_config(DeviceType::Output, self.device.output.as_deref())
+            .inspect_err(|e| println!("获取输出设备配置失败: {}", e))
+            .map(Into::into)
+            .unwrap_or_default();
+    }
+
+    fn cell() -> &'static RwLock<Arc<Config>> {
+        CONFIG.get_or_init(|| RwLock::new(Arc::new(Config::new())))
+    }
+
+    /// 当前配置的快照, 配置更新后需重新获取
+    pub fn get_instance() -> Arc<Config> {
+        Self::cell()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 加载错误与校验错误
+    pub fn diagnostics(&self) -> Vec<FieldError> {
+        let mut errors = self.load_errors.clone();
+        errors.extend(validate::validate(self));
+        errors
+    }
+
+    /// 校验配置, 错误逐项返回给前端
+    pub fn validate(&self) -> Result<(), AppError> {
+        let errors = validate::validate(self);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::InvalidConfig(errors))
+    }
+
+    /// 校验新配置, 将相对当前配置的修改写回配置文件, 成功后替换当前配置, 返回替换前的配置
+    ///
+    /// 未修改的配置项保持配置文件中的原值, 环境变量与命令行参数不会写入文件, 在写回后重新生效
+    pub fn update(config: Config) -> Result<Arc<Config>, AppError> {
+        config.validate()?;
+        let current = Self::get_instance();
+        let mut changes = layer::diff(&current.to_table()?, &config.to_table()?);
+        changes.extend(config.profile_changes(&changes));
+
+        let path = current.path.clone();
+        let mut table = Self::read_file(&path)?;
+        layer::apply(&mut table, &changes);
+        let text = toml::to_string_pretty(&table)
+            .map_err(|e| AppError::Config(format!("配置序列化失败: {}", e)))?;
+        Self::write_file(&path, &text)?;
+
+        let cli = layer::parse_args(std::env::args().skip(1), &Self::schema());
+        let mut config = Self::load(&path, &cli.overrides)?;
+        config.resolve_devices();
+        let mut current = Self::cell().write().unwrap_or_else(PoisonError::into_inner);
+        Ok(std::mem::replace(&mut *current, Arc::new(config)))
+    }
+}
+
+#[test]
+fn f() {
+    Config::get_instance();
+    Config::get_instance();
+    Config::get_instance();
+    Config::get_instance();
+}
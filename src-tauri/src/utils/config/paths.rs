@@ -0,0 +1,49 @@
+//! 应用目录, 由 Tauri 的 `app_config_dir`/`app_data_dir`/`app_log_dir` 提供
+//!
+//! 需在首次读取配置前调用 [`init`]; 未初始化时 (命令行工具、测试) 使用临时目录,
+//! 命令行工具可通过 `--config` 指定配置文件
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+struct AppDirs {
+    config: PathBuf,
+    data: PathBuf,
+    log: PathBuf,
+}
+
+static DIRS: OnceLock<AppDirs> = OnceLock::new();
+
+/// 设置应用目录, 重复调用时忽略
+pub fn init(config: PathBuf, data: PathBuf, log: PathBuf) {
+    DIRS.set(AppDirs { config, data, log }).ok();
+}
+
+fn fallback() -> PathBuf {
+    std::env::temp_dir().join(env!("CARGO_PKG_NAME"))
+}
+
+pub fn config_dir() -> PathBuf {
+    DIRS.get()
+        .map(|e| e.config.clone())
+        .unwrap_or_else(fallback)
+}
+
+pub fn data_dir() -> PathBuf {
+    DIRS.get().map(|e| e.data.clone()).unwrap_or_else(fallback)
+}
+
+pub fn log_dir() -> PathBuf {
+    DIRS.get()
+        .map(|e| e.log.clone())
+        .unwrap_or_else(|| fallback().join("logs"))
+}
+
+/// 相对路径以 `base` 为基准解析
+pub fn resolve(base: &Path, path: &Path) -> PathBuf {
+    if path.is_relative() {
+        base.join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
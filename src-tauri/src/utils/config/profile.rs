@@ -0,0 +1,144 @@
+//! 服务器配置: 按名称保存服务器地址、token 与音频参数, 切换时覆盖当前的 `websocket`、`mqtt` 与 `opus`
+
+use super::layer::Change;
+use super::{Config, MqttCfg, OpusCfg, WsCfg};
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileCfg {
+    pub websocket: WsCfg,
+    #[serde(default)]
+    pub mqtt: MqttCfg,
+    pub opus: OpusCfg,
+}
+
+impl Config {
+    /// 切换到指定的服务器配置
+    pub fn activate_profile(&self, name: &str) -> Result<Config, AppError> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or(AppError::InvalidInput(format!(
+                "未知的服务器配置: {}",
+                name
+            )))?;
+        let mut config = self.clone();
+        config.websocket = profile.websocket.clone();
+        config.mqtt = profile.mqtt.clone();
+        config.opus = profile.opus.clone();
+        config.profile = Some(name.to_string());
+        Ok(config)
+    }
+
+    /// 添加或覆盖服务器配置, 覆盖当前使用的配置时立即生效
+    pub fn add_profile(&self, name: &str, profile: ProfileCfg) -> Result<Config, AppError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::InvalidInput("服务器配置名称为空".to_string()));
+        }
+        let mut config = self.clone();
+        config.profiles.insert(name.to_string(), profile);
+        if config.profile.as_deref() == Some(name) {
+            return config.activate_profile(name);
+        }
+        Ok(config)
+    }
+
+    /// 删除服务器配置, 删除当前使用的配置时保留其参数, 但不再关联
+    pub fn remove_profile(&self, name: &str) -> Result<Config, AppError> {
+        let mut config = self.clone();
+        if config.profiles.remove(name).is_none() {
+            return Err(AppError::InvalidInput(format!(
+                "未知的服务器配置: {}",
+                name
+            )));
+        }
+        if config.profile.as_deref() == Some(name) {
+            config.profile = None;
+        }
+        Ok(config)
+    }
+
+    /// 将当前参数的修改同步到正在使用的服务器配置
+    pub(super) fn profile_changes(&self, changes: &[Change]) -> Vec<Change> {
+        let Some(name) = self
+            .profile
+            .as_ref()
+            .filter(|e| self.profiles.contains_key(*e))
+        else {
+            return vec![];
+        };
+        changes
+            .iter()
+            .filter(|(path, _)| {
+                matches!(
+                    path.first().map(String::as_str),
+                    Some("websocket" | "mqtt" | "opus")
+                )
+            })
+            .map(|(path, value)| {
+                let path = ["profiles", name]
+                    .into_iter()
+                    .map(String::from)
+                    .chain(path.iter().cloned())
+                    .collect();
+                (path, value.clone())
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn f() {
+    let mut config: Config = toml::from_str(super::DEFAULT_CONFIG).unwrap();
+    let staging = ProfileCfg {
+        websocket: WsCfg {
+            transport: "websocket".to_string(),
+            url: "wss://staging.example.com/ws".to_string(),
+            protocol_version: 2,
+            ping_interval: 0,
+            pong_timeout: 10000,
+            hello_timeout: 5000,
+            hello_retries: 1,
+            token: Some("staging-token".to_string()),
+            tls: Default::default(),
+            proxy: Default::default(),
+        },
+        mqtt: MqttCfg::default(),
+        opus: OpusCfg {
+            sample_rate: 24000,
+            frame_duration: 20,
+            ..Default::default()
+        },
+    };
+    assert!(config.add_profile(" ", staging.clone()).is_err());
+    assert!(config.activate_profile("staging").is_err());
+
+    config = config.add_profile("staging", staging.clone()).unwrap();
+    assert_eq!(config.profile, None);
+    config = config.activate_profile("staging").unwrap();
+    assert_eq!(config.profile.as_deref(), Some("staging"));
+    assert_eq!(config.websocket, staging.websocket);
+    assert_eq!(config.opus, staging.opus);
+
+    let mut changed = config.clone();
+    changed.opus.frame_duration = 60;
+    let changes = super::layer::diff(&config.to_table().unwrap(), &changed.to_table().unwrap());
+    let mut table = config.to_table().unwrap();
+    super::layer::apply(&mut table, &changed.profile_changes(&changes));
+    assert_eq!(
+        table["profiles"]["staging"]["opus"]["frame_duration"].as_integer(),
+        Some(60)
+    );
+
+    let text = toml::to_string_pretty(&config).unwrap();
+    let parsed: Config = toml::from_str(&text).unwrap();
+    assert_eq!(parsed.profiles, config.profiles);
+    assert_eq!(parsed.profile.as_deref(), Some("staging"));
+
+    config = config.remove_profile("staging").unwrap();
+    assert_eq!(config.profile, None);
+    assert_eq!(config.websocket.url, staging.websocket.url);
+    assert!(config.remove_profile("staging").is_err());
+}
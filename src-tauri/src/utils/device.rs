@@ -2,25 +2,15 @@ use cpal::{
     Device,
     traits::{DeviceTrait, HostTrait},
 };
-use std::default;
+use std::ops::Not;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DeviceConfig {
     pub sample_rate: u32,
     pub channels: usize,
     pub raw_config: Option<cpal::SupportedStreamConfig>,
 }
 
-impl default::Default for DeviceConfig {
-    fn default() -> Self {
-        DeviceConfig {
-            sample_rate: 0,
-            channels: 0,
-            raw_config: None,
-        }
-    }
-}
-
 impl From<cpal::SupportedStreamConfig> for DeviceConfig {
     fn from(config: cpal::SupportedStreamConfig) -> Self {
         DeviceConfig {
@@ -37,8 +27,18 @@ pub enum DeviceType {
     Output,
 }
 
-pub fn get_device(t: DeviceType) -> anyhow::Result<Device> {
+/// 获取音频设备, 指定名称时按名称查找, 否则使用系统默认设备
+pub fn get_device(t: DeviceType, name: Option<&str>) -> anyhow::Result<Device> {
     let host = cpal::default_host();
+    if let Some(name) = name.filter(|e| e.is_empty().not()) {
+        let mut devices = match t {
+            DeviceType::Input => host.input_devices()?,
+            DeviceType::Output => host.output_devices()?,
+        };
+        return devices
+            .find(|e| e.name().is_ok_and(|e| e == name))
+            .ok_or(anyhow::anyhow!("未找到设备: {}", name));
+    }
     match t {
         DeviceType::Input => {
             let device = host
@@ -55,8 +55,11 @@ pub fn get_device(t: DeviceType) -> anyhow::Result<Device> {
     }
 }
 
-pub fn get_device_config(t: DeviceType) -> anyhow::Result<cpal::SupportedStreamConfig> {
-    let device = get_device(t)?;
+pub fn get_device_config(
+    t: DeviceType,
+    name: Option<&str>,
+) -> anyhow::Result<cpal::SupportedStreamConfig> {
+    let device = get_device(t, name)?;
 
     match t {
         DeviceType::Input => {
@@ -69,3 +72,13 @@ pub fn get_device_config(t: DeviceType) -> anyhow::Result<cpal::SupportedStreamC
         }
     }
 }
+
+/// 列出可用设备名称
+pub fn list_devices(t: DeviceType) -> anyhow::Result<Vec<String>> {
+    let host = cpal::default_host();
+    let devices = match t {
+        DeviceType::Input => host.input_devices()?,
+        DeviceType::Output => host.output_devices()?,
+    };
+    Ok(devices.filter_map(|e| e.name().ok()).collect())
+}
@@ -0,0 +1,233 @@
+//! 二进制帧, 版本由 `hello` 中的 `version` 协商, 多字节字段均为网络字节序
+//!
+//! - 版本 1: 无头部, 整个消息为音频数据
+//! - 版本 2: `version: u16, type: u16, reserved: u32, timestamp: u32, payload_size: u32`
+//! - 版本 3: `type: u8, reserved: u8, payload_size: u16`
+
+pub const VERSIONS: [u32; 3] = [1, 2, 3];
+
+const V2_HEADER: usize = 16;
+const V3_HEADER: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadType {
+    Audio,
+    Json,
+    Other(u16),
+}
+
+impl From<u16> for PayloadType {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => PayloadType::Audio,
+            1 => PayloadType::Json,
+            e => PayloadType::Other(e),
+        }
+    }
+}
+
+impl From<PayloadType> for u16 {
+    fn from(value: PayloadType) -> Self {
+        match value {
+            PayloadType::Audio => 0,
+            PayloadType::Json => 1,
+            PayloadType::Other(e) => e,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryError {
+    UnsupportedVersion(u32),
+    /// 数据不足一个头部
+    TooShort(usize),
+    SizeMismatch {
+        declared: usize,
+        actual: usize,
+    },
+    /// 负载超出头部可表示的长度
+    TooLarge(usize),
+    /// 负载类型超出头部可表示的范围
+    InvalidType(u16),
+}
+
+impl std::fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryError::UnsupportedVersion(v) => write!(f, "不支持的二进制协议版本: {}", v),
+            BinaryError::TooShort(len) => write!(f, "二进制帧长度不足: {}", len),
+            BinaryError::SizeMismatch { declared, actual } => {
+                write!(f, "负载长度不符: 声明 {}, 实际 {}", declared, actual)
+            }
+            BinaryError::TooLarge(len) => write!(f, "负载过长: {}", len),
+            BinaryError::InvalidType(kind) => write!(f, "无效的负载类型: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+/// 二进制帧, 版本 1/3 不携带时间戳, 解码时为 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryFrame {
+    pub kind: PayloadType,
+    /// 毫秒时间戳, 下行用于回声消除对齐
+    pub timestamp: u32,
+    pub payload: Vec<u8>,
+}
+
+impl BinaryFrame {
+    pub fn audio(payload: Vec<u8>, timestamp: u32) -> Self {
+        Self {
+            kind: PayloadType::Audio,
+            timestamp,
+            payload,
+        }
+    }
+
+    pub fn encode(&self, version: u32) -> Result<Vec<u8>, BinaryError> {
+        let len = self.payload.len();
+        let mut data = match version {
+            1 => Vec::with_capacity(len),
+            2 => {
+                let size = u32::try_from(len).map_err(|_| BinaryError::TooLarge(len))?;
+                let mut data = Vec::with_capacity(V2_HEADER + len);
+                data.extend_from_slice(&2u16.to_be_bytes());
+                data.extend_from_slice(&u16::from(self.kind).to_be_bytes());
+                data.extend_from_slice(&0u32.to_be_bytes());
+                data.extend_from_slice(&self.timestamp.to_be_bytes());
+                data.extend_from_slice(&size.to_be_bytes());
+                data
+            }
+            3 => {
+                let size = u16::try_from(len).map_err(|_| BinaryError::TooLarge(len))?;
+                let kind = u16::from(self.kind);
+                let kind = u8::try_from(kind).map_err(|_| BinaryError::InvalidType(kind))?;
+                let mut data = Vec::with_capacity(V3_HEADER + len);
+                data.extend_from_slice(&[kind, 0]);
+                data.extend_from_slice(&size.to_be_bytes());
+                data
+            }
+            v => return Err(BinaryError::UnsupportedVersion(v)),
+        };
+        data.extend_from_slice(&self.payload);
+        Ok(data)
+    }
+
+    pub fn decode(version: u32, data: &[u8]) -> Result<Self, BinaryError> {
+        let (kind, timestamp, declared, header) = match version {
+            1 => return Ok(Self::audio(data.to_vec(), 0)),
+            2 => {
+                if data.len() < V2_HEADER {
+                    return Err(BinaryError::TooShort(data.len()));
+                }
+                let be32 =
+                    |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+                (
+                    u16::from_be_bytes([data[2], data[3]]),
+                    be32(8),
+                    be32(12) as usize,
+                    V2_HEADER,
+                )
+            }
+            3 => {
+                if data.len() < V3_HEADER {
+                    return Err(BinaryError::TooShort(data.len()));
+                }
+                (
+                    data[0] as u16,
+                    0,
+                    u16::from_be_bytes([data[2], data[3]]) as usize,
+                    V3_HEADER,
+                )
+            }
+            v => return Err(BinaryError::UnsupportedVersion(v)),
+        };
+        let payload = &data[header..];
+        if payload.len() != declared {
+            return Err(BinaryError::SizeMismatch {
+                declared,
+                actual: payload.len(),
+            });
+        }
+        Ok(Self {
+            kind: kind.into(),
+            timestamp,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+#[test]
+fn v1() {
+    let frame = BinaryFrame::audio(vec![1, 2, 3], 100);
+    let data = frame.encode(1).unwrap();
+    assert_eq!(data, [1, 2, 3]);
+    assert_eq!(
+        BinaryFrame::decode(1, &data).unwrap(),
+        BinaryFrame::audio(vec![1, 2, 3], 0)
+    );
+}
+
+#[test]
+fn v2() {
+    let frame = BinaryFrame::audio(vec![0xAA, 0xBB], 0x01020304);
+    let data = frame.encode(2).unwrap();
+    assert_eq!(
+        data,
+        [0, 2, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 0, 0, 0, 2, 0xAA, 0xBB]
+    );
+    assert_eq!(BinaryFrame::decode(2, &data).unwrap(), frame);
+
+    let json = BinaryFrame {
+        kind: PayloadType::Json,
+        timestamp: 7,
+        payload: b"{}".to_vec(),
+    };
+    assert_eq!(
+        BinaryFrame::decode(2, &json.encode(2).unwrap()).unwrap(),
+        json
+    );
+
+    assert_eq!(
+        BinaryFrame::decode(2, &data[..10]),
+        Err(BinaryError::TooShort(10))
+    );
+    assert_eq!(
+        BinaryFrame::decode(2, &data[..17]),
+        Err(BinaryError::SizeMismatch {
+            declared: 2,
+            actual: 1
+        })
+    );
+}
+
+#[test]
+fn v3() {
+    let frame = BinaryFrame::audio(vec![0xAA, 0xBB, 0xCC], 1000);
+    let data = frame.encode(3).unwrap();
+    assert_eq!(data, [0, 0, 0, 3, 0xAA, 0xBB, 0xCC]);
+    assert_eq!(
+        BinaryFrame::decode(3, &data).unwrap(),
+        BinaryFrame::audio(vec![0xAA, 0xBB, 0xCC], 0)
+    );
+
+    let other = BinaryFrame {
+        kind: PayloadType::Other(5),
+        timestamp: 0,
+        payload: vec![],
+    };
+    assert_eq!(
+        BinaryFrame::decode(3, &other.encode(3).unwrap()).unwrap(),
+        other
+    );
+
+    assert_eq!(
+        BinaryFrame::audio(vec![0; 70000], 0).encode(3),
+        Err(BinaryError::TooLarge(70000))
+    );
+    assert_eq!(
+        BinaryFrame::decode(4, &data),
+        Err(BinaryError::UnsupportedVersion(4))
+    );
+}
@@ -0,0 +1,213 @@
+//! 客户端 -> 服务器 消息
+
+use super::listen::{ListenMode, ListenState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AudioParams {
+    pub format: String,
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// 帧时长, 毫秒
+    pub frame_duration: u32,
+}
+
+impl Default for AudioParams {
+    fn default() -> Self {
+        Self {
+            format: "opus".to_string(),
+            sample_rate: 16000,
+            channels: 1,
+            frame_duration: 60,
+        }
+    }
+}
+
+impl AudioParams {
+    /// 每帧每声道的采样数
+    pub fn frame_size(&self) -> usize {
+        (self.sample_rate * self.frame_duration / 1000) as usize
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientMessage {
+    Hello {
+        version: u32,
+        transport: String,
+        audio_params: AudioParams,
+    },
+    Listen {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        state: ListenState,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mode: Option<ListenMode>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    Abort {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    Iot {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        descriptors: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        states: Option<Value>,
+    },
+    Goodbye {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+}
+
+impl ClientMessage {
+    /// `transport` 见 [`crate::utils::transport`], `version` 为请求的二进制协议版本
+    pub fn hello(transport: &str, version: u32, audio_params: AudioParams) -> Self {
+        Self::Hello {
+            version,
+            transport: transport.to_string(),
+            audio_params,
+        }
+    }
+
+    pub fn listen_start(session_id: Option<String>, mode: ListenMode) -> Self {
+        Self::Listen {
+            session_id,
+            state: ListenState::Start,
+            mode: Some(mode),
+            text: None,
+        }
+    }
+
+    pub fn listen_stop(session_id: Option<String>) -> Self {
+        Self::Listen {
+            session_id,
+            state: ListenState::Stop,
+            mode: None,
+            text: None,
+        }
+    }
+
+    /// 以文本代替语音输入
+    pub fn listen_detect(session_id: Option<String>, text: String) -> Self {
+        Self::Listen {
+            session_id,
+            state: ListenState::Detect,
+            mode: None,
+            text: Some(text),
+        }
+    }
+
+    pub fn abort(session_id: Option<String>, reason: Option<String>) -> Self {
+        Self::Abort { session_id, reason }
+    }
+
+    pub fn iot_states(session_id: Option<String>, states: Value) -> Self {
+        Self::Iot {
+            session_id,
+            descriptors: None,
+            states: Some(states),
+        }
+    }
+
+    pub fn iot_descriptors(session_id: Option<String>, descriptors: Value) -> Self {
+        Self::Iot {
+            session_id,
+            descriptors: Some(descriptors),
+            states: None,
+        }
+    }
+
+    pub fn goodbye(session_id: Option<String>) -> Self {
+        Self::Goodbye { session_id }
+    }
+}
+
+#[test]
+fn hello() {
+    let msg = ClientMessage::hello("websocket", 1, AudioParams::default());
+    let json = serde_json::to_value(&msg).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "type": "hello",
+            "version": 1,
+            "transport": "websocket",
+            "audio_params": {
+                "format": "opus",
+                "sample_rate": 16000,
+                "channels": 1,
+                "frame_duration": 60
+            }
+        })
+    );
+    assert_eq!(serde_json::from_value::<ClientMessage>(json).unwrap(), msg);
+}
+
+#[test]
+fn listen() {
+    use super::Frame;
+
+    let id = Some("id".to_string());
+    for msg in [
+        ClientMessage::listen_start(id.clone(), ListenMode::RealTime),
+        ClientMessage::listen_stop(id.clone()),
+        ClientMessage::listen_detect(id.clone(), "你好".to_string()),
+    ] {
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["type"], "listen");
+
+        let ClientMessage::Listen {
+            state, mode, text, ..
+        } = &msg
+        else {
+            unreachable!()
+        };
+        let Ok(Frame::ListenFrame(frame)) = Frame::try_from(json.clone()) else {
+            panic!("解析 listen 帧失败: {}", json);
+        };
+        assert_eq!(&frame.state, state);
+        assert_eq!(&frame.mode, mode);
+        assert_eq!(&frame.text, text);
+
+        assert_eq!(serde_json::from_value::<ClientMessage>(json).unwrap(), msg);
+    }
+}
+
+#[test]
+fn others() {
+    let id = Some("id".to_string());
+    for (msg, expected) in [
+        (
+            ClientMessage::abort(id.clone(), Some("wake_word_detected".to_string())),
+            serde_json::json!({"type": "abort", "session_id": "id", "reason": "wake_word_detected"}),
+        ),
+        (
+            ClientMessage::iot_states(id.clone(), serde_json::json!([])),
+            serde_json::json!({"type": "iot", "session_id": "id", "states": []}),
+        ),
+        (
+            ClientMessage::goodbye(id.clone()),
+            serde_json::json!({"type": "goodbye", "session_id": "id"}),
+        ),
+        (
+            ClientMessage::goodbye(None),
+            serde_json::json!({"type": "goodbye"}),
+        ),
+    ] {
+        assert_eq!(serde_json::to_value(&msg).unwrap(), expected);
+        assert_eq!(
+            serde_json::from_value::<ClientMessage>(expected).unwrap(),
+            msg
+        );
+    }
+}
@@ -0,0 +1,21 @@
+#![allow(dead_code)]
+
+use serde::{Deserialize, Serialize};
+
+/// 服务器主动结束会话
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GoodbyeFrame {
+    pub session_id: Option<String>,
+}
+
+#[test]
+fn f() {
+    let json = r#"
+    {
+        "session_id": "<会话ID>",
+        "type": "goodbye"
+    }
+    "#;
+    let frame: GoodbyeFrame = serde_json::from_str(json).unwrap();
+    println!("{:#?}", frame);
+}
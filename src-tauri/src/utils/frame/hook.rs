@@ -0,0 +1,64 @@
+//! 自定义帧处理钩子
+//!
+//! 应用代码可为内置类型之外的帧注册处理函数, 控制器收到 [`super::Frame::Unknown`] 时
+//! 按 `type` 分发, 无需修改控制器
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+pub type FrameHandler = Arc<dyn Fn(&Value) + Send + Sync>;
+
+static HANDLERS: OnceLock<RwLock<HashMap<String, FrameHandler>>> = OnceLock::new();
+
+fn handlers() -> &'static RwLock<HashMap<String, FrameHandler>> {
+    HANDLERS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// 注册帧处理函数, 同一类型重复注册时覆盖旧的处理函数
+pub fn register_frame_handler(
+    kind: impl Into<String>,
+    handler: impl Fn(&Value) + Send + Sync + 'static,
+) {
+    handlers()
+        .write()
+        .unwrap()
+        .insert(kind.into(), Arc::new(handler));
+}
+
+pub fn unregister_frame_handler(kind: &str) {
+    handlers().write().unwrap().remove(kind);
+}
+
+/// 分发帧, 返回是否有对应的处理函数
+pub fn dispatch(kind: &str, raw: &Value) -> bool {
+    // 先克隆处理函数再调用, 避免处理函数内注册/注销时死锁
+    let handler = handlers().read().unwrap().get(kind).cloned();
+    match handler {
+        Some(handler) => {
+            handler(raw);
+            true
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn f() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_ = count.clone();
+    register_frame_handler("hook-test", move |raw| {
+        assert_eq!(raw["value"], 1);
+        count_.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let raw = serde_json::json!({"type": "hook-test", "value": 1});
+    assert!(dispatch("hook-test", &raw));
+    assert!(!dispatch("hook-test-other", &raw));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    unregister_frame_handler("hook-test");
+    assert!(!dispatch("hook-test", &raw));
+}
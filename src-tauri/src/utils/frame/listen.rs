@@ -5,24 +5,24 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ListenMode {
     Auto,
     Manual,
     RealTime,
 }
-impl ToString for ListenMode {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ListenMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ListenMode::Auto => "auto".to_string(),
-            ListenMode::Manual => "manual".to_string(),
-            ListenMode::RealTime => "realtime".to_string(),
+            ListenMode::Auto => write!(f, "auto"),
+            ListenMode::Manual => write!(f, "manual"),
+            ListenMode::RealTime => write!(f, "realtime"),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ListenState {
     Start,
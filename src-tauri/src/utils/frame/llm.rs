@@ -0,0 +1,23 @@
+#![allow(dead_code)]
+
+use serde::{Deserialize, Serialize};
+
+/// 大模型回复附带的情绪
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LlmFrame {
+    pub emotion: Option<String>,
+    pub text: Option<String>,
+}
+
+#[test]
+fn f() {
+    let json = r#"
+    {
+        "type": "llm",
+        "emotion": "happy",
+        "text": "😀"
+    }
+    "#;
+    let frame: LlmFrame = serde_json::from_str(json).unwrap();
+    println!("{:#?}", frame);
+}
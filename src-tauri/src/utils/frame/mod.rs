@@ -1,40 +1,139 @@
+use goodbye::GoodbyeFrame;
 use listen::ListenFrame;
+use llm::LlmFrame;
+use serde::Serialize;
 use serde_json::Value;
-use tracing::debug;
+use stt::SttFrame;
 use tts::TtsFrame;
 
+pub mod binary;
+pub mod client;
+pub mod goodbye;
+pub mod hook;
 pub mod listen;
+pub mod llm;
+pub mod stt;
 pub mod tts;
 
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Frame {
     ListenFrame(ListenFrame),
     TtsFrame(TtsFrame),
-    Error,
+    SttFrame(SttFrame),
+    LlmFrame(LlmFrame),
+    GoodbyeFrame(GoodbyeFrame),
+    /// 未内置的帧类型, 可通过 [`hook::register_frame_handler`] 处理
+    Unknown { kind: String, raw: Value },
+    /// 帧类型已知但内容解析失败
+    Invalid { raw: Value, error: String },
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    /// 缺少 `type` 字段
+    MissingType,
+    UnknownType(String),
+    Invalid { kind: String, error: String },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::MissingType => write!(f, "缺少 type 字段"),
+            FrameError::UnknownType(kind) => write!(f, "未知帧类型: {}", kind),
+            FrameError::Invalid { kind, error } => write!(f, "{} 帧解析失败: {}", kind, error),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
 impl From<ListenFrame> for Frame {
     fn from(listen_frame: ListenFrame) -> Self {
         Frame::ListenFrame(listen_frame)
     }
 }
 
-impl From<Value> for Frame {
-    fn from(json: Value) -> Self {
-        let frame_type = json["type"].as_str().unwrap_or_default();
+impl TryFrom<Value> for Frame {
+    type Error = FrameError;
+
+    fn try_from(json: Value) -> Result<Self, Self::Error> {
+        let frame_type = json["type"]
+            .as_str()
+            .ok_or(FrameError::MissingType)?
+            .to_string();
 
-        match frame_type {
+        let invalid = |e: serde_json::Error| FrameError::Invalid {
+            kind: frame_type.clone(),
+            error: e.to_string(),
+        };
+
+        match frame_type.as_str() {
             "listen" => serde_json::from_value::<ListenFrame>(json)
-                .map(|e| Frame::ListenFrame(e))
-                .unwrap_or(Frame::Error),
+                .map(Frame::ListenFrame)
+                .map_err(invalid),
             "tts" => serde_json::from_value::<TtsFrame>(json)
-                .map(|e| Frame::TtsFrame(e))
-                .unwrap_or(Frame::Error),
-            _ => {
-                debug!("未知帧类型:\n{:#}", json);
-                Frame::Error
-            }
+                .map(Frame::TtsFrame)
+                .map_err(invalid),
+            "stt" => serde_json::from_value::<SttFrame>(json)
+                .map(Frame::SttFrame)
+                .map_err(invalid),
+            "llm" => serde_json::from_value::<LlmFrame>(json)
+                .map(Frame::LlmFrame)
+                .map_err(invalid),
+            "goodbye" => serde_json::from_value::<GoodbyeFrame>(json)
+                .map(Frame::GoodbyeFrame)
+                .map_err(invalid),
+            _ => Err(FrameError::UnknownType(frame_type)),
         }
     }
 }
+
+impl Frame {
+    /// 解析控制帧, 失败时以 [`Frame::Unknown`]/[`Frame::Invalid`] 保留原始内容
+    pub fn parse(json: Value) -> Self {
+        match Frame::try_from(json.clone()) {
+            Ok(frame) => frame,
+            Err(FrameError::UnknownType(kind)) => Frame::Unknown { kind, raw: json },
+            Err(e) => Frame::Invalid {
+                raw: json,
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+#[test]
+fn f() {
+    use serde_json::json;
+
+    assert!(matches!(
+        Frame::parse(json!({"type": "stt", "text": "你好"})),
+        Frame::SttFrame(_)
+    ));
+
+    let raw = json!({"type": "mcp", "payload": {}});
+    let Frame::Unknown { kind, raw: r } = Frame::parse(raw.clone()) else {
+        panic!()
+    };
+    assert_eq!(kind, "mcp");
+    assert_eq!(r, raw);
+
+    let raw = json!({"type": "tts", "state": "unknown"});
+    assert!(matches!(
+        Frame::try_from(raw.clone()),
+        Err(FrameError::Invalid { .. })
+    ));
+    let Frame::Invalid { raw: r, error } = Frame::parse(raw.clone()) else {
+        panic!()
+    };
+    assert_eq!(r, raw);
+    assert!(error.contains("tts"));
+
+    assert_eq!(
+        Frame::try_from(json!({"text": ""})).unwrap_err(),
+        FrameError::MissingType
+    );
+    assert!(matches!(Frame::parse(json!(1)), Frame::Invalid { .. }));
+}
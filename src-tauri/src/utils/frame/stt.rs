@@ -0,0 +1,21 @@
+#![allow(dead_code)]
+
+use serde::{Deserialize, Serialize};
+
+/// 服务器语音识别结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SttFrame {
+    pub text: String,
+}
+
+#[test]
+fn f() {
+    let json = r#"
+    {
+        "type": "stt",
+        "text": "<识别文本>"
+    }
+    "#;
+    let frame: SttFrame = serde_json::from_str(json).unwrap();
+    println!("{:#?}", frame);
+}
@@ -13,13 +13,13 @@ pub enum TtsState {
     SentenceEnd,
 }
 
-impl ToString for TtsState {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for TtsState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TtsState::Start => "start".to_string(),
-            TtsState::Stop => "stop".to_string(),
-            TtsState::SentenceStart => "sentence_start".to_string(),
-            TtsState::SentenceEnd => "sentence_end".to_string(),
+            TtsState::Start => write!(f, "start"),
+            TtsState::Stop => write!(f, "stop"),
+            TtsState::SentenceStart => write!(f, "sentence_start"),
+            TtsState::SentenceEnd => write!(f, "sentence_end"),
         }
     }
 }
@@ -0,0 +1,202 @@
+//! 对话历史记录, 每个会话以 JSON Lines 形式保存为 `<dir>/<session_id>.jsonl`
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::ops::Not;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// 会话ID用作文件名, 不能为空, 不能包含路径分隔符或 `..`
+pub fn is_valid_session_id(session_id: &str) -> bool {
+    session_id.is_empty().not()
+        && session_id.contains(['/', '\\']).not()
+        && session_id.contains("..").not()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// 用户语音识别文本
+    User,
+    /// 服务器 TTS 语句
+    Assistant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub session_id: String,
+    /// Unix 时间戳, 毫秒
+    pub timestamp: u64,
+    pub role: Role,
+    pub text: String,
+    pub emotion: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(session_id: &str, role: Role, text: String, emotion: Option<String>) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|e| e.as_millis() as u64)
+                .unwrap_or_default(),
+            role,
+            text,
+            emotion,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub entries: usize,
+    /// 会话第一句文本, 用于列表展示
+    pub preview: String,
+}
+
+pub struct History {
+    dir: PathBuf,
+    // 串行化文件读写
+    lock: Mutex<()>,
+}
+
+impl History {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn session_path(&self, session_id: &str) -> anyhow::Result<PathBuf> {
+        if is_valid_session_id(session_id).not() {
+            return Err(anyhow!("非法的会话ID: {}", session_id));
+        }
+        Ok(self.dir.join(format!("{}.jsonl", session_id)))
+    }
+
+    fn read_session(&self, session_id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        let content = std::fs::read_to_string(self.session_path(session_id)?)?;
+        content
+            .lines()
+            .filter(|e| e.trim().is_empty().not())
+            .map(|e| Ok(serde_json::from_str(e)?))
+            .collect()
+    }
+
+    fn session_ids(&self) -> anyhow::Result<Vec<String>> {
+        if self.dir.exists().not() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "jsonl")
+                && let Some(id) = path.file_stem().and_then(|e| e.to_str())
+            {
+                ids.push(id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().map_err(|e| anyhow!("{}", e))?;
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.session_path(&entry.session_id)?)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// 按最近更新时间倒序列出会话
+    pub fn list(&self) -> anyhow::Result<Vec<SessionSummary>> {
+        let _guard = self.lock.lock().map_err(|e| anyhow!("{}", e))?;
+        let mut sessions = Vec::new();
+        for id in self.session_ids()? {
+            let entries = self.read_session(&id)?;
+            let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+                continue;
+            };
+            sessions.push(SessionSummary {
+                session_id: id.clone(),
+                started_at: first.timestamp,
+                updated_at: last.timestamp,
+                entries: entries.len(),
+                preview: first.text.clone(),
+            });
+        }
+        sessions.sort_by_key(|e| std::cmp::Reverse(e.updated_at));
+        Ok(sessions)
+    }
+
+    pub fn transcript(&self, session_id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        let _guard = self.lock.lock().map_err(|e| anyhow!("{}", e))?;
+        self.read_session(session_id)
+    }
+
+    /// 不区分大小写的全文搜索
+    pub fn search(&self, query: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+        let _guard = self.lock.lock().map_err(|e| anyhow!("{}", e))?;
+        let query = query.to_lowercase();
+        let mut result = Vec::new();
+        for id in self.session_ids()? {
+            result.extend(
+                self.read_session(&id)?
+                    .into_iter()
+                    .filter(|e| e.text.to_lowercase().contains(&query)),
+            );
+        }
+        result.sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+        Ok(result)
+    }
+
+    pub fn delete(&self, session_id: &str) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().map_err(|e| anyhow!("{}", e))?;
+        std::fs::remove_file(self.session_path(session_id)?)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn f() {
+    let dir = std::env::temp_dir().join("t-app-history-test");
+    std::fs::remove_dir_all(&dir).ok();
+    let history = History::new(dir);
+
+    history
+        .append(&HistoryEntry::new("a", Role::User, "今天天气怎么样".into(), None))
+        .unwrap();
+    history
+        .append(&HistoryEntry::new(
+            "a",
+            Role::Assistant,
+            "今天晴天".into(),
+            Some("happy".into()),
+        ))
+        .unwrap();
+    history
+        .append(&HistoryEntry::new("b", Role::User, "Hello".into(), None))
+        .unwrap();
+
+    let sessions = history.list().unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    let transcript = history.transcript("a").unwrap();
+    assert_eq!(transcript.len(), 2);
+    assert_eq!(transcript[1].role, Role::Assistant);
+    assert_eq!(transcript[1].emotion.as_deref(), Some("happy"));
+
+    assert_eq!(history.search("天气").unwrap().len(), 1);
+    assert_eq!(history.search("hello").unwrap().len(), 1);
+
+    history.delete("a").unwrap();
+    assert_eq!(history.list().unwrap().len(), 1);
+    assert!(history.transcript("../b").is_err());
+}
@@ -1,23 +1,35 @@
 use super::config::Config;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use crate::error::AppError;
+use std::sync::OnceLock;
+use tracing::level_filters::LevelFilter;
+use tracing::{info, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, reload, util::SubscriberInitExt};
+
+/// 日志级别热更新句柄
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
 
 pub fn init_logger() {
-    let log_file: std::fs::File = Config::get_instance().logger.clone().into();
-    let level: tracing::level_filters::LevelFilter = Config::get_instance().logger.clone().into();
+    let log_file = std::fs::File::try_from(Config::get_instance().logger.clone())
+        .inspect_err(|e| println!("{}, 日志仅输出到控制台", e))
+        .ok();
+    let level: LevelFilter = Config::get_instance().logger.clone().into();
+    let (level, handle) = reload::Layer::new(level);
+    LEVEL_HANDLE.set(handle).ok();
 
     // 配置输出到文件的 fmt 层
-    let file_fmt = tracing_subscriber::fmt::layer()
-        .with_target(false)
-        .with_level(true)
-        .with_file(true)
-        .with_line_number(true)
-        .with_timer(tracing_subscriber::fmt::time::LocalTime::new(
-            time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
-        ))
-        .with_ansi(false)
-        .with_writer(std::sync::Mutex::new(log_file))
-        .with_thread_names(true);
+    let file_fmt = log_file.map(|log_file| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_level(true)
+            .with_file(true)
+            .with_line_number(true)
+            .with_timer(tracing_subscriber::fmt::time::LocalTime::new(
+                time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+            ))
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(log_file))
+            .with_thread_names(true)
+    });
 
     // 配置输出到控制台的 fmt 层
     let console_fmt = tracing_subscriber::fmt::layer()
@@ -36,6 +48,24 @@ pub fn init_logger() {
         .with(console_fmt)
         .init();
     info!(">>>>>日志初始化完成<<<<<");
+    for e in Config::get_instance().diagnostics() {
+        warn!("配置错误: {}", e);
+    }
+
+    info!(
+        "工作目录: {}",
+        std::env::current_dir().unwrap_or_default().display()
+    );
+}
 
-    info!("工作目录: {}", std::env::current_dir().unwrap().display());
+/// 修改日志级别, 日志文件路径与写入模式需重启后生效
+pub fn set_level(level: LevelFilter) -> Result<(), AppError> {
+    let Some(handle) = LEVEL_HANDLE.get() else {
+        return Ok(());
+    };
+    handle
+        .reload(level)
+        .map_err(|e| AppError::Config(format!("日志级别修改失败: {}", e)))?;
+    info!("日志级别: {}", level);
+    Ok(())
 }
@@ -0,0 +1,218 @@
+//! 内存回环传输, 由 [`LoopbackPeer`] 扮演服务器, 用于在没有网络的情况下测试对话管线
+
+use crate::error::AppError;
+use crate::types::AsyncMutex;
+use crate::utils::frame::Frame;
+use crate::utils::frame::client::AudioParams;
+use crate::utils::transport::{AudioPacket, AudioStream, FrameStream, Transport};
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use serde_json::{Value, json};
+use std::ops::Not;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::sync::mpsc;
+
+/// 客户端发出的消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum Uplink {
+    Text(String),
+    Audio(Vec<u8>),
+}
+
+pub struct LoopbackTransport {
+    session_id: String,
+    is_connected: bool,
+    /// 服务器 `hello` 回复的下行音频参数
+    server_audio_params: Option<AudioParams>,
+    closed: Arc<Notify>,
+    uplink: mpsc::UnboundedSender<Uplink>,
+    frame_recver: Arc<AsyncMutex<mpsc::UnboundedReceiver<Frame>>>,
+    audio_recver: Arc<AsyncMutex<mpsc::UnboundedReceiver<AudioPacket>>>,
+}
+
+/// 回环传输的服务器端
+pub struct LoopbackPeer {
+    closed: Arc<Notify>,
+    uplink: mpsc::UnboundedReceiver<Uplink>,
+    frame_sender: mpsc::UnboundedSender<Frame>,
+    audio_sender: mpsc::UnboundedSender<AudioPacket>,
+}
+
+/// 创建一对相连的客户端与服务器端
+pub fn pair(session_id: &str) -> (LoopbackTransport, LoopbackPeer) {
+    let closed = Arc::new(Notify::new());
+    let (uplink_sender, uplink) = mpsc::unbounded_channel();
+    let (frame_sender, frame_recver) = mpsc::unbounded_channel();
+    let (audio_sender, audio_recver) = mpsc::unbounded_channel();
+    (
+        LoopbackTransport {
+            session_id: session_id.to_string(),
+            is_connected: false,
+            server_audio_params: None,
+            closed: closed.clone(),
+            uplink: uplink_sender,
+            frame_recver: Arc::new(AsyncMutex::new(frame_recver)),
+            audio_recver: Arc::new(AsyncMutex::new(audio_recver)),
+        },
+        LoopbackPeer {
+            closed,
+            uplink,
+            frame_sender,
+            audio_sender,
+        },
+    )
+}
+
+impl LoopbackTransport {
+    pub fn with_server_audio_params(mut self, audio_params: AudioParams) -> Self {
+        self.server_audio_params = Some(audio_params);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for LoopbackTransport {
+    async fn connect(&mut self) -> Result<String, AppError> {
+        self.is_connected = true;
+        Ok(self.session_id.clone())
+    }
+
+    async fn close(&mut self) -> Result<(), AppError> {
+        self.is_connected = false;
+        Ok(())
+    }
+
+    fn get_closed_notify(&self) -> Arc<Notify> {
+        self.closed.clone()
+    }
+
+    async fn send_audio(&self, data: Vec<u8>) -> Result<(), AppError> {
+        self.send(Uplink::Audio(data))
+    }
+
+    async fn send_text(&self, message: String) -> Result<(), AppError> {
+        self.send(Uplink::Text(message))
+    }
+
+    async fn frames(&self) -> Option<FrameStream> {
+        self.is_connected
+            .then(|| shared_stream(self.frame_recver.clone()))
+    }
+
+    async fn audio(&self) -> Option<AudioStream> {
+        self.is_connected
+            .then(|| shared_stream(self.audio_recver.clone()))
+    }
+
+    async fn get_server_audio_params(&self) -> Option<AudioParams> {
+        self.server_audio_params.clone()
+    }
+
+    async fn get_session_id(&self) -> Option<String> {
+        self.is_connected.then(|| self.session_id.clone())
+    }
+
+    async fn is_connected(&self) -> bool {
+        self.is_connected
+    }
+}
+
+/// 回环传输在重新连接时沿用同一通道, 各次连接的流共享接收端
+fn shared_stream<T: Send + 'static>(
+    recver: Arc<AsyncMutex<mpsc::UnboundedReceiver<T>>>,
+) -> BoxStream<'static, T> {
+    futures_util::stream::unfold(recver, |recver| async move {
+        let item = recver.lock().await.recv().await;
+        item.map(|e| (e, recver))
+    })
+    .boxed()
+}
+
+impl LoopbackTransport {
+    fn send(&self, message: Uplink) -> Result<(), AppError> {
+        if self.is_connected.not() {
+            return Err(AppError::NotConnected);
+        }
+        self.uplink
+            .send(message)
+            .map_err(|_| AppError::Send("回环传输已关闭".to_string()))
+    }
+}
+
+impl LoopbackPeer {
+    /// 下发任意控制帧
+    pub fn send_frame(&self, frame: Value) {
+        self.frame_sender.send(Frame::parse(frame)).ok();
+    }
+
+    pub fn send_audio(&self, data: Vec<u8>) {
+        self.send_audio_packet(AudioPacket::new(data, None));
+    }
+
+    /// 下发带服务器时间戳的音频数据
+    pub fn send_audio_packet(&self, packet: AudioPacket) {
+        self.audio_sender.send(packet).ok();
+    }
+
+    /// 服务器结束会话
+    pub fn goodbye(&self) {
+        self.send_frame(json!({"type": "goodbye"}));
+        self.closed.notify_waiters();
+    }
+
+    /// 读取客户端发出的消息, 客户端释放后返回 `None`
+    pub async fn recv(&mut self) -> Option<Uplink> {
+        self.uplink.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<Uplink> {
+        self.uplink.try_recv().ok()
+    }
+}
+
+#[tokio::test]
+async fn f() {
+    use crate::utils::frame::client::ClientMessage;
+
+    let (transport, mut peer) = pair("loopback");
+    let mut transport: Box<dyn Transport> = Box::new(transport);
+    assert_eq!(
+        transport.send_audio(vec![1]).await.unwrap_err().code(),
+        "NOT_CONNECTED"
+    );
+    assert_eq!(transport.connect().await.unwrap(), "loopback");
+    assert_eq!(
+        transport.get_session_id().await.as_deref(),
+        Some("loopback")
+    );
+
+    transport.send_audio(vec![1, 2, 3]).await.unwrap();
+    transport
+        .send_message(ClientMessage::goodbye(None))
+        .await
+        .unwrap();
+    assert_eq!(peer.recv().await, Some(Uplink::Audio(vec![1, 2, 3])));
+    assert_eq!(
+        peer.recv().await,
+        Some(Uplink::Text(r#"{"type":"goodbye"}"#.to_string()))
+    );
+
+    let mut frames = transport.frames().await.unwrap();
+    let mut audio = transport.audio().await.unwrap();
+    peer.send_audio(vec![4, 5]);
+    assert_eq!(audio.next().await, Some(AudioPacket::new(vec![4, 5], None)));
+
+    let closed = transport.get_closed_notify();
+    let notified = closed.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+    peer.goodbye();
+    notified.await;
+    assert!(matches!(frames.next().await, Some(Frame::GoodbyeFrame(_))));
+
+    transport.close().await.unwrap();
+    assert!(transport.is_connected().await.not());
+    assert!(transport.frames().await.is_none());
+    assert_eq!(peer.try_recv(), None);
+}
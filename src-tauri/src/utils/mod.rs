@@ -1,5 +1,12 @@
 pub mod config;
 pub mod device;
 pub mod frame;
+pub mod history;
 pub mod log;
+pub mod loopback;
+pub mod mqtt;
+pub mod proxy;
+pub mod queue;
+pub mod tls;
+pub mod transport;
 pub mod ws;
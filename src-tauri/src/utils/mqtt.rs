@@ -0,0 +1,584 @@
+//! MQTT + UDP 传输: 控制帧经 MQTT 收发, 音频数据经 AES-128-CTR 加密后经 UDP 收发
+//!
+//! 服务器在 `hello` 回复中下发 UDP 参数:
+//! `{"type": "hello", "transport": "udp", "udp": {"server", "port", "key", "nonce"}}`
+//!
+//! UDP 数据包为 16 字节头部加密文, 头部同时作为 CTR 初始向量, 多字节字段均为网络字节序:
+//! `type: u8, flags: u8, payload_size: u16, ssrc: u32, timestamp: u32, sequence: u32`
+
+use crate::error::AppError;
+use crate::types::SharedAsyncRwLock;
+use crate::utils::config::{Config, MqttCfg};
+use crate::utils::frame::Frame;
+use crate::utils::frame::client::{AudioParams, ClientMessage};
+use crate::utils::queue::{self, DropCounters, Dropped, RingReceiver};
+use crate::utils::transport::{self, AudioPacket, AudioStream, FrameStream, Transport, UDP};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde::Deserialize;
+use std::ops::Not;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio::sync::futures::Notified;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, trace, warn};
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+const HEADER_SIZE: usize = 16;
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+/// `hello` 中请求的协议版本, UDP 数据包头部固定, 不使用 [`crate::utils::frame::binary`]
+const PROTOCOL_VERSION: u32 = 3;
+
+/// 服务器 `hello` 回复中的 UDP 参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct UdpInfo {
+    pub server: String,
+    pub port: u16,
+    /// 16 字节密钥, 十六进制
+    pub key: String,
+    /// 16 字节 nonce, 十六进制, 作为上行数据包头部的模板
+    pub nonce: String,
+}
+
+/// UDP 音频数据包的加解密与序号校验
+pub struct UdpCrypto {
+    key: [u8; 16],
+    nonce: [u8; 16],
+    local_sequence: AtomicU32,
+    remote_sequence: AtomicU32,
+}
+
+impl UdpCrypto {
+    pub fn new(key: &str, nonce: &str) -> Result<Self, AppError> {
+        Ok(Self {
+            key: decode_hex(key)?,
+            nonce: decode_hex(nonce)?,
+            local_sequence: AtomicU32::new(0),
+            remote_sequence: AtomicU32::new(0),
+        })
+    }
+
+    /// 加密一帧音频数据, 序号从 1 开始递增
+    pub fn encrypt(&self, payload: &[u8], timestamp: u32) -> Result<Vec<u8>, AppError> {
+        let size = u16::try_from(payload.len())
+            .map_err(|_| AppError::Send(format!("音频数据过长: {}", payload.len())))?;
+        let sequence = self.local_sequence.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let mut packet = Vec::with_capacity(HEADER_SIZE + payload.len());
+        packet.extend_from_slice(&self.nonce);
+        packet[2..4].copy_from_slice(&size.to_be_bytes());
+        packet[8..12].copy_from_slice(&timestamp.to_be_bytes());
+        packet[12..16].copy_from_slice(&sequence.to_be_bytes());
+        packet.extend_from_slice(payload);
+
+        let (header, data) = packet.split_at_mut(HEADER_SIZE);
+        Aes128Ctr::new(&self.key.into(), (&*header).into()).apply_keystream(data);
+        Ok(packet)
+    }
+
+    /// 解密一个数据包, 重复或过期的数据包返回 `None`
+    pub fn decrypt(&self, packet: &[u8]) -> Result<Option<AudioPacket>, AppError> {
+        if packet.len() < HEADER_SIZE {
+            return Err(AppError::Codec(format!(
+                "UDP 数据包长度不足: {}",
+                packet.len()
+            )));
+        }
+        let (header, data) = packet.split_at(HEADER_SIZE);
+        let size = u16::from_be_bytes([header[2], header[3]]) as usize;
+        if size != data.len() {
+            return Err(AppError::Codec(format!(
+                "UDP 负载长度不符: 声明 {}, 实际 {}",
+                size,
+                data.len()
+            )));
+        }
+
+        let timestamp = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let sequence = u32::from_be_bytes([header[12], header[13], header[14], header[15]]);
+        let remote = self.remote_sequence.load(Ordering::Relaxed);
+        if sequence <= remote {
+            debug!("丢弃过期的音频数据包: {} <= {}", sequence, remote);
+            return Ok(None);
+        }
+        if sequence != remote + 1 {
+            debug!("音频数据包序号不连续: {} -> {}", remote, sequence);
+        }
+        self.remote_sequence.store(sequence, Ordering::Relaxed);
+
+        let mut data = data.to_vec();
+        Aes128Ctr::new(&self.key.into(), header.into()).apply_keystream(&mut data);
+        Ok(Some(AudioPacket::new(data, Some(timestamp))))
+    }
+}
+
+fn decode_hex(text: &str) -> Result<[u8; 16], AppError> {
+    let invalid = || AppError::Connect(format!("无效的 UDP 密钥参数: {}", text));
+    if text.len() != 32 || text.is_ascii().not() {
+        return Err(invalid());
+    }
+    let mut bytes = [0u8; 16];
+    for (i, e) in bytes.iter_mut().enumerate() {
+        *e = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+/// 解析 `host:port`, 端口缺省为 1883
+fn parse_endpoint(endpoint: &str) -> Result<(String, u16), AppError> {
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) => port
+            .parse()
+            .map(|port| (host.to_string(), port))
+            .map_err(|_| AppError::Connect(format!("无效的 MQTT 地址: {}", endpoint))),
+        None => Ok((endpoint.to_string(), DEFAULT_PORT)),
+    }
+}
+
+struct UdpChannel {
+    socket: UdpSocket,
+    crypto: UdpCrypto,
+    /// 通道建立时刻, 上行时间戳以此为起点
+    connected_at: Instant,
+}
+
+pub struct MqttUdpProtocol {
+    cfg: MqttCfg,
+    /// `hello` 中声明的上行音频参数
+    audio_params: AudioParams,
+    /// 服务器 `hello` 回复的下行音频参数
+    server_audio_params: SharedAsyncRwLock<Option<AudioParams>>,
+    /// 服务器 `hello` 回复的 UDP 参数
+    udp_info: SharedAsyncRwLock<Option<UdpInfo>>,
+    /// 每次等待服务器 `hello` 的超时
+    hello_timeout: Duration,
+    /// 超时后重发 `hello` 的次数
+    hello_retries: u32,
+    is_connected: SharedAsyncRwLock<bool>,
+    session_id: SharedAsyncRwLock<Option<String>>,
+
+    hello_received: Arc<Notify>,
+    closed: Arc<Notify>,
+
+    client: Option<AsyncClient>,
+    udp: Option<Arc<UdpChannel>>,
+
+    mqtt_handle: Option<tauri::async_runtime::JoinHandle<()>>,
+    udp_handle: Option<tauri::async_runtime::JoinHandle<()>>,
+
+    frame_recver: SharedAsyncRwLock<Option<mpsc::Receiver<Frame>>>,
+    audio_recver: SharedAsyncRwLock<Option<RingReceiver<AudioPacket>>>,
+    dropped: DropCounters,
+}
+
+impl MqttUdpProtocol {
+    pub fn new(cfg: MqttCfg) -> Self {
+        Self {
+            cfg,
+            audio_params: AudioParams::default(),
+            server_audio_params: SharedAsyncRwLock::new(None.into()),
+            udp_info: SharedAsyncRwLock::new(None.into()),
+            hello_timeout: Duration::from_secs(5),
+            hello_retries: 0,
+            is_connected: SharedAsyncRwLock::new(false.into()),
+            session_id: SharedAsyncRwLock::new(None.into()),
+
+            hello_received: Arc::new(Notify::new()),
+            closed: Arc::new(Notify::new()),
+
+            client: None,
+            udp: None,
+
+            mqtt_handle: None,
+            udp_handle: None,
+
+            frame_recver: SharedAsyncRwLock::new(None.into()),
+            audio_recver: SharedAsyncRwLock::new(None.into()),
+            dropped: DropCounters::default(),
+        }
+    }
+
+    pub fn with_audio_params(mut self, audio_params: AudioParams) -> Self {
+        self.audio_params = audio_params;
+        self
+    }
+
+    /// 每次等待 `timeout`, 超时后重发 `hello` 至多 `retries` 次
+    pub fn with_handshake(mut self, timeout: Duration, retries: u32) -> Self {
+        self.hello_timeout = timeout;
+        self.hello_retries = retries;
+        self
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.mqtt.clone())
+            .with_audio_params(config.opus.audio_params())
+            .with_handshake(
+                Duration::from_millis(config.websocket.hello_timeout),
+                config.websocket.hello_retries,
+            )
+    }
+
+    /// 订阅主题并发送 `hello`, 超时后重发, MQTT 连接失败时立即返回
+    async fn handshake(&self, mut failed: Pin<&mut Notified<'_>>) -> Result<(), AppError> {
+        let Some(client) = &self.client else {
+            return Err(AppError::NotConnected);
+        };
+        if self.cfg.subscribe_topic.is_empty().not() {
+            client
+                .subscribe(&self.cfg.subscribe_topic, QoS::AtMostOnce)
+                .await
+                .map_err(|e| AppError::Connect(e.to_string()))?;
+        }
+
+        for attempt in 0..=self.hello_retries {
+            if attempt > 0 {
+                warn!("等待服务器hello超时, 第 {} 次重发", attempt);
+            }
+            let hello_msg = ClientMessage::hello(UDP, PROTOCOL_VERSION, self.audio_params.clone());
+            debug!("发送hello消息: {:?}", &hello_msg);
+            self.send_message(hello_msg).await?;
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.hello_timeout) => {}
+                _ = failed.as_mut() => {
+                    return Err(AppError::Connect(format!(
+                        "MQTT 连接失败: {}",
+                        self.cfg.endpoint
+                    )));
+                }
+                _ = self.hello_received.notified() => return Ok(()),
+            }
+        }
+        Err(AppError::HandshakeTimeout)
+    }
+
+    /// 握手失败时断开连接, 并等待事件处理线程结束
+    async fn abort(&mut self) {
+        if let Some(t) = self.mqtt_handle.take() {
+            t.abort();
+            t.await.ok();
+        }
+        self.client = None;
+        self.frame_recver.write().await.take();
+        *self.is_connected.write().await = false;
+        *self.session_id.write().await = None;
+    }
+
+    /// 按 `hello` 回复中的参数建立 UDP 通道
+    async fn open_udp(&mut self) -> Result<(), AppError> {
+        let info = self.udp_info.write().await.take().ok_or(AppError::Connect(
+            "服务器 hello 未包含 UDP 参数".to_string(),
+        ))?;
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(|e| AppError::Connect(format!("UDP 绑定失败: {}", e)))?;
+        socket
+            .connect((info.server.as_str(), info.port))
+            .await
+            .map_err(|e| AppError::Connect(format!("UDP 连接失败: {}", e)))?;
+        info!("UDP 音频通道已建立: {}:{}", info.server, info.port);
+
+        let udp = Arc::new(UdpChannel {
+            socket,
+            crypto: UdpCrypto::new(&info.key, &info.nonce)?,
+            connected_at: Instant::now(),
+        });
+        let (audio_sender, audio_recv) = queue::ring_channel::<AudioPacket>(
+            queue::AUDIO_CAPACITY,
+            self.dropped.downlink_packets.clone(),
+        );
+        self.audio_recver.write().await.replace(audio_recv);
+
+        let udp_ = udp.clone();
+        self.udp_handle
+            .replace(tauri::async_runtime::spawn(async move {
+                let mut buf = vec![0u8; 4096];
+                loop {
+                    let len = match udp_.socket.recv(&mut buf).await {
+                        Ok(len) => len,
+                        Err(e) => {
+                            warn!("UDP 接收失败: {}", e);
+                            break;
+                        }
+                    };
+                    match udp_.crypto.decrypt(&buf[..len]) {
+                        Ok(Some(packet)) => {
+                            if audio_sender.send(packet).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("UDP 数据包解析失败: {}", e),
+                    }
+                }
+                debug!("UDP 输入处理线程结束");
+            }));
+        self.udp = Some(udp);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for MqttUdpProtocol {
+    async fn connect(&mut self) -> Result<String, AppError> {
+        if self.is_connected().await {
+            warn!("MQTT 已连接，拒绝重复连接");
+            return self
+                .session_id
+                .read()
+                .await
+                .as_ref()
+                .map(|e| Ok(e.to_owned()))
+                .unwrap_or(Err(AppError::Connect("未查询到 session_id".to_string())));
+        }
+        if self.cfg.client_id.is_empty() {
+            return Err(AppError::Connect("MQTT client_id 为空".to_string()));
+        }
+
+        let (host, port) = parse_endpoint(&self.cfg.endpoint)?;
+        let mut options = MqttOptions::new(&self.cfg.client_id, host, port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if self.cfg.username.is_empty().not() {
+            options.set_credentials(&self.cfg.username, &self.cfg.password);
+        }
+        let (client, mut eventloop) = AsyncClient::new(options, 16);
+
+        let connected = self.is_connected.clone();
+        let id = self.session_id.clone();
+        let server_audio_params = self.server_audio_params.clone();
+        let udp_info = self.udp_info.clone();
+        // 上次握手失败后迟到的 hello 不应唤醒本次连接
+        self.hello_received = Arc::new(Notify::new());
+        let hello_received = self.hello_received.clone();
+        let closed = self.closed.clone();
+
+        let (frame_sender, frame_recv) = mpsc::channel::<Frame>(queue::CONTROL_CAPACITY);
+        self.frame_recver.write().await.replace(frame_recv);
+
+        // 先登记关闭通知, 以便握手期间及时发现连接失败
+        let closed_ = self.closed.clone();
+        let failed = closed_.notified();
+        tokio::pin!(failed);
+        failed.as_mut().enable();
+
+        self.mqtt_handle
+            .replace(tauri::async_runtime::spawn(async move {
+                loop {
+                    let publish = match eventloop.poll().await {
+                        Ok(Event::Incoming(Packet::Publish(publish))) => publish,
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            debug!("MQTT 已连接");
+                            continue;
+                        }
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                        Ok(event) => {
+                            trace!("MQTT 事件: {:?}", event);
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!("MQTT 连接断开: {}", e);
+                            closed.notify_waiters();
+                            break;
+                        }
+                    };
+                    let Ok(data) = serde_json::from_slice::<serde_json::Value>(&publish.payload)
+                    else {
+                        warn!("MQTT 消息解析失败: {}", publish.topic);
+                        continue;
+                    };
+
+                    if data["type"] == "hello" && connected.read().await.not() {
+                        *connected.write().await = true;
+                        *id.write().await = data["session_id"].as_str().map(|e| e.to_string());
+                        *server_audio_params.write().await =
+                            serde_json::from_value(data["audio_params"].clone()).ok();
+                        *udp_info.write().await = serde_json::from_value(data["udp"].clone())
+                            .inspect_err(|e| warn!("UDP 参数解析失败: {}", e))
+                            .ok();
+                        hello_received.notify_one();
+                    }
+
+                    let is_goodbye = data["type"] == "goodbye";
+                    let frame = Frame::parse(data);
+                    trace!("控制帧:\n{:#?}", frame);
+                    frame_sender.send(frame).await.unwrap_or_else(|e| {
+                        error!("发送控制帧失败: {}", e);
+                    });
+
+                    if is_goodbye {
+                        debug!("服务器结束会话");
+                        closed.notify_waiters();
+                    }
+                }
+                debug!("MQTT 事件处理线程结束");
+            }));
+
+        self.client = Some(client);
+        if let Err(e) = self.handshake(failed).await {
+            self.abort().await;
+            return Err(e);
+        }
+
+        if let Err(e) = self.open_udp().await {
+            self.close().await.ok();
+            return Err(e);
+        }
+
+        Ok(self
+            .session_id
+            .read()
+            .await
+            .clone()
+            .unwrap_or("None".to_string()))
+    }
+
+    async fn close(&mut self) -> Result<(), AppError> {
+        if self.is_connected().await.not() {
+            warn!("MQTT 未连接，拒绝重复关闭");
+            return Ok(());
+        }
+
+        self.is_connected.write().await.clone_from(&false);
+
+        if let Some(client) = self.client.take() {
+            // 连接已断开时事件处理线程已结束, 无需断开
+            client
+                .disconnect()
+                .await
+                .unwrap_or_else(|e| debug!("MQTT 断开失败: {}", e));
+        }
+        if let Some(mut recver) = self.frame_recver.write().await.take() {
+            recver.close();
+        }
+        if let Some(mut recver) = self.audio_recver.write().await.take() {
+            recver.close();
+        }
+
+        if let Some(t) = self.mqtt_handle.take() {
+            t.await
+                .map_err(|e| AppError::Internal(format!("MQTT 事件处理线程关闭失败: {}", e)))?;
+        }
+        // UDP 无连接状态, 接收线程只能中止
+        if let Some(t) = self.udp_handle.take() {
+            t.abort();
+        }
+        self.udp = None;
+
+        Ok(())
+    }
+
+    fn get_closed_notify(&self) -> Arc<Notify> {
+        self.closed.clone()
+    }
+
+    async fn send_audio(&self, data: Vec<u8>) -> Result<(), AppError> {
+        let Some(udp) = &self.udp else {
+            return Err(AppError::NotConnected);
+        };
+        let timestamp = udp.connected_at.elapsed().as_millis() as u32;
+        let packet = udp.crypto.encrypt(&data, timestamp)?;
+        udp.socket
+            .send(&packet)
+            .await
+            .map(|_| ())
+            .map_err(|e| AppError::Send(format!("发送音频数据失败: {}", e)))
+    }
+
+    async fn send_text(&self, message: String) -> Result<(), AppError> {
+        let Some(client) = &self.client else {
+            return Err(AppError::NotConnected);
+        };
+        client
+            .publish(
+                &self.cfg.publish_topic,
+                QoS::AtMostOnce,
+                false,
+                message.into_bytes(),
+            )
+            .await
+            .map_err(|e| AppError::Send(format!("发送文本消息失败: {}", e)))
+    }
+
+    async fn frames(&self) -> Option<FrameStream> {
+        self.frame_recver
+            .write()
+            .await
+            .take()
+            .map(transport::receiver_stream)
+    }
+
+    async fn audio(&self) -> Option<AudioStream> {
+        let recver = self.audio_recver.write().await.take()?;
+        Some(recver.into_stream())
+    }
+
+    async fn get_server_audio_params(&self) -> Option<AudioParams> {
+        self.server_audio_params.read().await.clone()
+    }
+
+    async fn get_session_id(&self) -> Option<String> {
+        self.session_id.read().await.clone()
+    }
+
+    async fn is_connected(&self) -> bool {
+        *self.is_connected.read().await
+    }
+
+    fn dropped(&self) -> Dropped {
+        self.dropped.snapshot()
+    }
+}
+
+impl Drop for MqttUdpProtocol {
+    fn drop(&mut self) {
+        tokio::task::block_in_place(|| {
+            tauri::async_runtime::block_on(self.close()).unwrap_or_else(|e| {
+                error!("MQTT 实例关闭失败: {}", e);
+            });
+            debug!("MQTT 实例释放资源");
+        })
+    }
+}
+
+#[test]
+fn f() {
+    let key = "00112233445566778899aabbccddeeff";
+    let nonce = "01000000123456780000000000000000";
+    let client = UdpCrypto::new(key, nonce).unwrap();
+    let server = UdpCrypto::new(key, nonce).unwrap();
+
+    let first = client.encrypt(&[1, 2, 3, 4], 100).unwrap();
+    assert_eq!(
+        first[..HEADER_SIZE],
+        [1, 0, 0, 4, 0x12, 0x34, 0x56, 0x78, 0, 0, 0, 100, 0, 0, 0, 1]
+    );
+    assert_ne!(first[HEADER_SIZE..], [1, 2, 3, 4]);
+    let second = client.encrypt(&[5], 160).unwrap();
+    assert_eq!(second[12..16], [0, 0, 0, 2]);
+
+    assert_eq!(
+        server.decrypt(&second).unwrap(),
+        Some(AudioPacket::new(vec![5], Some(160)))
+    );
+    // 乱序到达的旧数据包被丢弃
+    assert_eq!(server.decrypt(&first).unwrap(), None);
+    assert!(server.decrypt(&second[..10]).is_err());
+    assert!(server.decrypt(&first[..18]).is_err());
+
+    assert!(UdpCrypto::new("0011", nonce).is_err());
+    assert_eq!(
+        parse_endpoint("mqtt.example.com").unwrap(),
+        ("mqtt.example.com".to_string(), 1883)
+    );
+    assert_eq!(
+        parse_endpoint("127.0.0.1:8883").unwrap(),
+        ("127.0.0.1".to_string(), 8883)
+    );
+}
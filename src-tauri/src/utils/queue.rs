@@ -0,0 +1,196 @@
+//! 有界队列, 消费者停滞时限制内存占用
+//!
+//! - 音频: 满时丢弃最旧的数据, 优先播放或发送最新的音频, 见 [`ring_channel`]
+//! - 下行控制帧: 同样丢弃最旧的数据, 避免消费者停滞时读取暂停, 心跳 pong 无法处理
+//! - 上行控制消息: 满时等待消费者, 不丢弃, 使用 [`tokio::sync::mpsc::channel`]
+//!
+//! 丢弃的数量记录在 [`DropCounters`] 中, 经诊断信息展示
+
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// 音频队列容量 (包), 按 60 ms 帧约 30 s
+pub const AUDIO_CAPACITY: usize = 512;
+/// 控制帧与上行控制消息队列容量
+pub const CONTROL_CAPACITY: usize = 64;
+
+/// 丢弃数量
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dropped {
+    /// 上行音频包
+    pub uplink_packets: u64,
+    /// 下行音频包
+    pub downlink_packets: u64,
+    /// 编码跟不上采集时丢弃的麦克风采样
+    pub input_samples: u64,
+    /// 下行控制帧
+    pub control_frames: u64,
+}
+
+impl std::ops::Add for Dropped {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            uplink_packets: self.uplink_packets + rhs.uplink_packets,
+            downlink_packets: self.downlink_packets + rhs.downlink_packets,
+            input_samples: self.input_samples + rhs.input_samples,
+            control_frames: self.control_frames + rhs.control_frames,
+        }
+    }
+}
+
+/// 丢弃计数, 由各队列共享
+#[derive(Debug, Default)]
+pub struct DropCounters {
+    pub uplink_packets: Arc<AtomicU64>,
+    pub downlink_packets: Arc<AtomicU64>,
+    pub input_samples: Arc<AtomicU64>,
+    pub control_frames: Arc<AtomicU64>,
+}
+
+impl DropCounters {
+    pub fn snapshot(&self) -> Dropped {
+        Dropped {
+            uplink_packets: self.uplink_packets.load(Ordering::Relaxed),
+            downlink_packets: self.downlink_packets.load(Ordering::Relaxed),
+            input_samples: self.input_samples.load(Ordering::Relaxed),
+            control_frames: self.control_frames.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    /// 发送端或接收端已释放
+    closed: AtomicBool,
+    notify: Notify,
+    dropped: Arc<AtomicU64>,
+}
+
+/// 满时丢弃最旧数据的单生产者单消费者队列, 丢弃数量累加到 `dropped`
+pub fn ring_channel<T>(
+    capacity: usize,
+    dropped: Arc<AtomicU64>,
+) -> (RingSender<T>, RingReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity: capacity.max(1),
+        closed: AtomicBool::new(false),
+        notify: Notify::new(),
+        dropped,
+    });
+    (RingSender(shared.clone()), RingReceiver(shared))
+}
+
+pub struct RingSender<T>(Arc<Shared<T>>);
+
+impl<T> RingSender<T> {
+    /// 接收端已释放时返回原数据
+    pub fn send(&self, value: T) -> Result<(), T> {
+        if self.0.closed.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        {
+            let mut queue = self.0.queue.lock().unwrap();
+            if queue.len() >= self.0.capacity {
+                queue.pop_front();
+                self.0.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            queue.push_back(value);
+        }
+        self.0.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Drop for RingSender<T> {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+        self.0.notify.notify_one();
+    }
+}
+
+pub struct RingReceiver<T>(Arc<Shared<T>>);
+
+impl<T> RingReceiver<T> {
+    pub fn try_recv(&self) -> Option<T> {
+        self.0.queue.lock().unwrap().pop_front()
+    }
+
+    /// 等待数据, 发送端释放且队列为空时返回 `None`
+    pub async fn recv(&self) -> Option<T> {
+        loop {
+            if let Some(value) = self.try_recv() {
+                return Some(value);
+            }
+            if self.0.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            self.0.notify.notified().await;
+        }
+    }
+
+    /// 关闭队列, 之后的发送均失败
+    pub fn close(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+    }
+
+    /// 转换为流, 发送端释放且队列为空时结束
+    pub fn into_stream(self) -> BoxStream<'static, T>
+    where
+        T: Send + 'static,
+    {
+        futures_util::stream::unfold(self, |recver| async move {
+            recver.recv().await.map(|e| (e, recver))
+        })
+        .boxed()
+    }
+}
+
+impl<T> Drop for RingReceiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[tokio::test]
+async fn f() {
+    let counters = DropCounters::default();
+    let dropped = counters.downlink_packets.clone();
+    let (tx, rx) = ring_channel(2, dropped.clone());
+    for i in 0..5 {
+        tx.send(i).unwrap();
+    }
+    assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    assert_eq!(rx.recv().await, Some(3));
+    assert_eq!(rx.try_recv(), Some(4));
+    assert_eq!(rx.try_recv(), None);
+
+    tx.send(5).unwrap();
+    drop(tx);
+    assert_eq!(rx.recv().await, Some(5));
+    assert_eq!(rx.recv().await, None);
+
+    let (tx, rx) = ring_channel(2, dropped.clone());
+    tx.send(6).unwrap();
+    drop(tx);
+    assert_eq!(rx.into_stream().collect::<Vec<_>>().await, [6]);
+
+    let (tx, _) = ring_channel::<u8>(1, dropped);
+    assert_eq!(tx.send(1), Err(1));
+
+    let total = counters.snapshot()
+        + Dropped {
+            downlink_packets: 1,
+            input_samples: 2,
+            ..Default::default()
+        };
+    assert_eq!((total.downlink_packets, total.input_samples), (4, 2));
+}
@@ -0,0 +1,240 @@
+//! `wss://` 连接的 TLS 配置
+//!
+//! - 信任内置根证书与 `ca_file` 中的证书
+//! - 设置 `client_cert`/`client_key` 时进行双向 TLS
+//! - 设置 `pins` 时, 证书链校验通过后还需校验所用的证书路径 (服务器证书、中间证书、根证书)
+//!   中任一公钥与固定值匹配, 服务器附带但未参与校验的证书不计入
+
+use crate::error::AppError;
+use crate::utils::config::TlsCfg;
+use crate::utils::config::paths;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+/// 公钥固定值可带的前缀
+const PIN_PREFIX: &str = "sha256/";
+
+/// 解析公钥固定值: SPKI 的 SHA-256 摘要, base64 编码, 可带 `sha256/` 前缀
+pub fn parse_pin(pin: &str) -> Option<[u8; 32]> {
+    let pin = pin.trim();
+    let pin = pin.strip_prefix(PIN_PREFIX).unwrap_or(pin);
+    STANDARD.decode(pin).ok()?.try_into().ok()
+}
+
+/// 计算证书的公钥固定值, 格式与 `pins` 一致
+pub fn spki_pin(cert: &CertificateDer) -> Option<String> {
+    spki_digest(cert).map(|e| format!("{}{}", PIN_PREFIX, STANDARD.encode(e)))
+}
+
+fn spki_digest(cert: &CertificateDer) -> Option<[u8; 32]> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    Some(sha256(cert.subject_public_key_info().as_ref()))
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0; 32];
+    out.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, data).as_ref());
+    out
+}
+
+/// 为 SPKI 内容补上 SEQUENCE 头部, 根证书只保存 SPKI 的内容部分
+fn der_sequence(contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x30];
+    let len = contents.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|e| **e == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+/// 按配置创建 TLS 客户端配置, 相对路径以应用配置目录为基准
+pub fn client_config(cfg: &TlsCfg) -> Result<Arc<ClientConfig>, AppError> {
+    let provider = Arc::new(default_provider());
+
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Some(path) = &cfg.ca_file {
+        for cert in read_certs(path)? {
+            roots
+                .add(cert)
+                .map_err(|e| AppError::Config(format!("CA 证书无效 {}: {}", path.display(), e)))?;
+        }
+    }
+    let roots = Arc::new(roots);
+    let webpki = WebPkiServerVerifier::builder_with_provider(roots.clone(), provider.clone())
+        .build()
+        .map_err(|e| AppError::Config(format!("证书校验器创建失败: {}", e)))?;
+    let verifier: Arc<dyn ServerCertVerifier> = if cfg.pins.is_empty() {
+        webpki
+    } else {
+        let pins = cfg
+            .pins
+            .iter()
+            .map(|e| parse_pin(e).ok_or_else(|| AppError::Config(format!("公钥固定值无效: {}", e))))
+            .collect::<Result<_, _>>()?;
+        Arc::new(PinnedVerifier {
+            inner: webpki,
+            roots,
+            algs: provider.signature_verification_algorithms,
+            pins,
+        })
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| AppError::Config(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+    let config = match (&cfg.client_cert, &cfg.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+            .map_err(|e| AppError::Config(format!("客户端证书无效: {}", e)))?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => {
+            return Err(AppError::Config(
+                "client_cert 与 client_key 需同时设置".to_string(),
+            ));
+        }
+    };
+    Ok(Arc::new(config))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, AppError> {
+    let path = paths::resolve(&paths::config_dir(), path);
+    let certs = CertificateDer::pem_file_iter(&path)
+        .and_then(|e| e.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| AppError::Config(format!("证书读取失败 {}: {}", path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(AppError::Config(format!("未找到证书: {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, AppError> {
+    let path = paths::resolve(&paths::config_dir(), path);
+    PrivateKeyDer::from_pem_file(&path)
+        .map_err(|e| AppError::Config(format!("私钥读取失败 {}: {}", path.display(), e)))
+}
+
+/// 在证书链校验之上检查公钥固定值
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    algs: WebPkiSupportedAlgorithms,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedVerifier {
+    fn pinned(&self, path: &webpki::VerifiedPath) -> bool {
+        std::iter::once(path.end_entity().subject_public_key_info())
+            .chain(
+                path.intermediate_certificates()
+                    .map(|e| e.subject_public_key_info()),
+            )
+            .map(|e| sha256(e.as_ref()))
+            .chain(std::iter::once(sha256(&der_sequence(
+                path.anchor().subject_public_key_info.as_ref(),
+            ))))
+            .any(|e| self.pins.contains(&e))
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        // 存在多条有效路径时, 任一路径匹配即可
+        let cert = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let pinned = |path: &webpki::VerifiedPath| {
+            if self.pinned(path) {
+                Ok(())
+            } else {
+                Err(webpki::Error::UnknownIssuer)
+            }
+        };
+        let matched = cert.verify_for_usage(
+            self.algs.all,
+            &self.roots.roots,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            None,
+            Some(&pinned),
+        );
+        if matched.is_ok() {
+            Ok(verified)
+        } else {
+            warn!(
+                "服务器公钥与固定值不匹配: {}",
+                spki_pin(end_entity).unwrap_or_default()
+            );
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[test]
+fn f() {
+    let pin = "sha256/".to_string() + &STANDARD.encode([7u8; 32]);
+    assert_eq!(parse_pin(&pin), Some([7u8; 32]));
+    assert_eq!(parse_pin(&pin[PIN_PREFIX.len()..]), Some([7u8; 32]));
+    assert_eq!(parse_pin("sha256/AAAA"), None);
+    assert_eq!(parse_pin("not base64"), None);
+}
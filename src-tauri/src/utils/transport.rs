@@ -0,0 +1,107 @@
+//! 传输层: 控制帧与音频数据的收发, 按 `hello` 中的 `transport` 选择实现
+//!
+//! - `websocket`: 控制帧与音频数据共用一条 WebSocket 连接, 见 [`WebsocketProtocol`]
+//! - `udp`: 控制帧经 MQTT 收发, 音频数据经 AES-CTR 加密的 UDP 收发, 见 [`MqttUdpProtocol`]
+//!
+//! 测试可使用内存回环传输, 见 [`crate::utils::loopback`]
+
+use crate::error::AppError;
+use crate::types::SharedAsyncRwLock;
+use crate::utils::config::Config;
+use crate::utils::frame::Frame;
+use crate::utils::frame::client::{AudioParams, ClientMessage};
+use crate::utils::mqtt::MqttUdpProtocol;
+use crate::utils::queue::Dropped;
+use crate::utils::ws::WebsocketProtocol;
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, mpsc};
+
+pub const WEBSOCKET: &str = "websocket";
+pub const UDP: &str = "udp";
+pub const TRANSPORTS: [&str; 2] = [WEBSOCKET, UDP];
+
+pub type SharedTransport = SharedAsyncRwLock<Box<dyn Transport>>;
+
+/// 下行控制帧流, 连接关闭后结束
+pub type FrameStream = BoxStream<'static, Frame>;
+/// 下行音频流, 连接关闭后结束
+pub type AudioStream = BoxStream<'static, AudioPacket>;
+
+/// 下行音频数据包
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioPacket {
+    pub payload: Vec<u8>,
+    /// 服务器毫秒时间戳, 用于回声消除对齐, 协议不携带时为空
+    pub timestamp: Option<u32>,
+}
+
+impl AudioPacket {
+    pub fn new(payload: Vec<u8>, timestamp: Option<u32>) -> Self {
+        Self { payload, timestamp }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync {
+    /// 建立连接并完成 `hello` 握手, 返回 session_id
+    async fn connect(&mut self) -> Result<String, AppError>;
+
+    async fn close(&mut self) -> Result<(), AppError>;
+
+    /// 连接被服务器关闭或会话结束时通知, 需在连接建立后取得
+    fn get_closed_notify(&self) -> Arc<Notify>;
+
+    async fn send_audio(&self, data: Vec<u8>) -> Result<(), AppError>;
+
+    async fn send_text(&self, message: String) -> Result<(), AppError>;
+
+    async fn send_message(&self, message: ClientMessage) -> Result<(), AppError> {
+        let text = serde_json::to_string(&message)
+            .map_err(|e| AppError::Internal(format!("消息序列化失败: {}", e)))?;
+        self.send_text(text).await
+    }
+
+    /// 取得本次连接的下行控制帧流, 每次连接只能取得一次, 未连接时为空
+    ///
+    /// 流不持有传输层的锁, 读取时可同时关闭连接
+    async fn frames(&self) -> Option<FrameStream>;
+
+    /// 取得本次连接的下行音频流, 每次连接只能取得一次, 未连接时为空
+    async fn audio(&self) -> Option<AudioStream>;
+
+    /// 服务器下行音频参数, 服务器未声明时为空
+    async fn get_server_audio_params(&self) -> Option<AudioParams>;
+
+    async fn get_session_id(&self) -> Option<String>;
+
+    /// 最近一次心跳的往返时延, 传输层不发送心跳时为空
+    async fn get_rtt(&self) -> Option<Duration> {
+        None
+    }
+
+    async fn is_connected(&self) -> bool;
+
+    /// 队列溢出时丢弃的音频数量
+    fn dropped(&self) -> Dropped {
+        Dropped::default()
+    }
+}
+
+/// 将通道接收端转换为流, 发送端释放后结束
+pub fn receiver_stream<T: Send + 'static>(recver: mpsc::Receiver<T>) -> BoxStream<'static, T> {
+    futures_util::stream::unfold(recver, |mut recver| async move {
+        recver.recv().await.map(|e| (e, recver))
+    })
+    .boxed()
+}
+
+/// 按 `websocket.transport` 创建传输层
+pub fn from_config(config: &Config) -> Box<dyn Transport> {
+    match config.websocket.transport.as_str() {
+        UDP => Box::new(MqttUdpProtocol::from_config(config)),
+        _ => Box::new(WebsocketProtocol::from_config(config)),
+    }
+}
@@ -1,18 +1,54 @@
+use crate::error::AppError;
 use crate::types::SharedAsyncRwLock;
+use crate::utils::config::{Config, ProxyCfg, TlsCfg};
+use crate::utils::frame::binary::{self, BinaryFrame, PayloadType};
+use crate::utils::frame::client::{AudioParams, ClientMessage};
+use crate::utils::proxy;
+use crate::utils::queue::{self, DropCounters, Dropped, RingReceiver, RingSender};
+use crate::utils::tls;
+use crate::utils::transport::{AudioPacket, AudioStream, FrameStream, Transport, WEBSOCKET};
 use futures_util::{SinkExt, StreamExt};
-use serde_json::json;
 use std::ops::Not;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Notify;
 use tokio::sync::mpsc;
-use tokio_tungstenite::connect_async;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderValue, header::AUTHORIZATION};
 use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{Connector, client_async_tls_with_config};
 use tracing::trace;
 use tracing::warn;
 use tracing::{debug, error, info};
 
 pub struct WebsocketProtocol {
     websocket_url: String,
+    /// 握手时以 `Authorization: Bearer <token>` 发送
+    token: Option<String>,
+    /// `wss://` 连接的 TLS 设置
+    tls: TlsCfg,
+    proxy: ProxyCfg,
+    /// `hello` 中声明的上行音频参数
+    audio_params: AudioParams,
+    /// 服务器 `hello` 回复的下行音频参数
+    server_audio_params: SharedAsyncRwLock<Option<AudioParams>>,
+    /// `hello` 中请求的二进制协议版本
+    protocol_version: u32,
+    /// 协商后的二进制协议版本, 服务器未回复版本时沿用请求的版本
+    binary_version: SharedAsyncRwLock<u32>,
+    /// 连接建立时刻, 上行时间戳以此为起点
+    connected_at: Instant,
+    /// 心跳间隔, 为零时不发送心跳
+    ping_interval: Duration,
+    /// 等待 pong 的超时, 超时视为连接已断开
+    pong_timeout: Duration,
+    /// 最近一次心跳的往返时延
+    rtt: SharedAsyncRwLock<Option<Duration>>,
+    /// 每次等待服务器 `hello` 的超时
+    hello_timeout: Duration,
+    /// 超时后重发 `hello` 的次数
+    hello_retries: u32,
     is_connected: SharedAsyncRwLock<bool>,
     session_id: SharedAsyncRwLock<Option<String>>,
 
@@ -21,17 +57,34 @@ pub struct WebsocketProtocol {
 
     input_handle: Option<tauri::async_runtime::JoinHandle<()>>,
     output_handle: Option<tauri::async_runtime::JoinHandle<()>>,
+    ping_handle: Option<tauri::async_runtime::JoinHandle<()>>,
 
     // 消息通道
-    msg_sender: Option<mpsc::UnboundedSender<Message>>,
-    frame_recver: SharedAsyncRwLock<Option<mpsc::UnboundedReceiver<crate::utils::frame::Frame>>>,
-    audio_recver: SharedAsyncRwLock<Option<mpsc::UnboundedReceiver<Vec<u8>>>>,
+    msg_sender: Option<mpsc::Sender<Message>>,
+    /// 上行音频, 发送跟不上时丢弃最旧的数据
+    audio_sender: Option<RingSender<Vec<u8>>>,
+    frame_recver: SharedAsyncRwLock<Option<RingReceiver<crate::utils::frame::Frame>>>,
+    audio_recver: SharedAsyncRwLock<Option<RingReceiver<AudioPacket>>>,
+    dropped: DropCounters,
 }
 
 impl WebsocketProtocol {
     pub fn new(websocket_url: String) -> Self {
         Self {
             websocket_url,
+            token: None,
+            tls: TlsCfg::default(),
+            proxy: ProxyCfg::default(),
+            audio_params: AudioParams::default(),
+            server_audio_params: SharedAsyncRwLock::new(None.into()),
+            protocol_version: 1,
+            binary_version: SharedAsyncRwLock::new(1.into()),
+            connected_at: Instant::now(),
+            ping_interval: Duration::ZERO,
+            pong_timeout: Duration::ZERO,
+            rtt: SharedAsyncRwLock::new(None.into()),
+            hello_timeout: Duration::from_secs(5),
+            hello_retries: 0,
             is_connected: SharedAsyncRwLock::new(false.into()),
             session_id: SharedAsyncRwLock::new(None.into()),
 
@@ -40,14 +93,124 @@ impl WebsocketProtocol {
 
             input_handle: None,
             output_handle: None,
+            ping_handle: None,
 
             msg_sender: None,
+            audio_sender: None,
             frame_recver: SharedAsyncRwLock::new(None.into()),
             audio_recver: SharedAsyncRwLock::new(None.into()),
+            dropped: DropCounters::default(),
         }
     }
 
-    pub async fn connect(&mut self) -> Result<String, String> {
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.filter(|e| e.is_empty().not());
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsCfg) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: ProxyCfg) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn with_audio_params(mut self, audio_params: AudioParams) -> Self {
+        self.audio_params = audio_params;
+        self
+    }
+
+    pub fn with_protocol_version(mut self, version: u32) -> Self {
+        self.protocol_version = version;
+        self
+    }
+
+    /// `interval` 为零时不发送心跳
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.ping_interval = interval;
+        self.pong_timeout = timeout;
+        self
+    }
+
+    /// 每次等待 `timeout`, 超时后重发 `hello` 至多 `retries` 次
+    pub fn with_handshake(mut self, timeout: Duration, retries: u32) -> Self {
+        self.hello_timeout = timeout;
+        self.hello_retries = retries;
+        self
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.websocket.url.clone())
+            .with_token(config.websocket.token.clone())
+            .with_tls(config.websocket.tls.clone())
+            .with_proxy(config.websocket.proxy.clone())
+            .with_audio_params(config.opus.audio_params())
+            .with_protocol_version(config.websocket.protocol_version)
+            .with_keepalive(
+                Duration::from_millis(config.websocket.ping_interval),
+                Duration::from_millis(config.websocket.pong_timeout),
+            )
+            .with_handshake(
+                Duration::from_millis(config.websocket.hello_timeout),
+                config.websocket.hello_retries,
+            )
+    }
+
+    /// 协商后的二进制协议版本
+    pub async fn get_binary_version(&self) -> u32 {
+        *self.binary_version.read().await
+    }
+
+    /// 发送 `hello` 并等待服务器回复, 超时后重发
+    async fn handshake(&self) -> Result<(), AppError> {
+        for attempt in 0..=self.hello_retries {
+            if attempt > 0 {
+                warn!("等待服务器hello超时, 第 {} 次重发", attempt);
+            }
+            let hello_msg =
+                ClientMessage::hello(WEBSOCKET, self.protocol_version, self.audio_params.clone());
+            debug!("发送hello消息: {:?}", &hello_msg);
+            self.send_message(hello_msg).await?;
+
+            let hello = tokio::time::timeout(self.hello_timeout, self.hello_received.notified());
+            if hello.await.is_ok() {
+                return Ok(());
+            }
+        }
+        Err(AppError::HandshakeTimeout)
+    }
+
+    /// 握手失败时关闭连接, 并等待所有后台线程结束
+    async fn abort(&mut self) {
+        // 服务器可能不响应关闭, 输入处理线程无法自行结束
+        for t in [self.ping_handle.take(), self.input_handle.take()]
+            .into_iter()
+            .flatten()
+        {
+            t.abort();
+            t.await.ok();
+        }
+        if let Some(sender) = self.msg_sender.take() {
+            sender.try_send(Message::Close(None)).ok();
+        }
+        self.audio_sender.take();
+        self.frame_recver.write().await.take();
+        self.audio_recver.write().await.take();
+        // 发送端全部释放后输出处理线程结束, 连接随之关闭
+        if let Some(t) = self.output_handle.take() {
+            t.await.ok();
+        }
+        *self.is_connected.write().await = false;
+        *self.session_id.write().await = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for WebsocketProtocol {
+    async fn connect(&mut self) -> Result<String, AppError> {
         if self.is_connected().await {
             warn!("WebSocket 已连接，拒绝重复连接");
             return self
@@ -56,82 +219,213 @@ impl WebsocketProtocol {
                 .await
                 .as_ref()
                 .map(|e| Ok(e.to_owned()))
-                .unwrap_or(Err("未查询到 session_id".to_string()));
+                .unwrap_or(Err(AppError::Connect("未查询到 session_id".to_string())));
         }
 
         let url = self.websocket_url.clone();
-        match connect_async(&url).await {
+        let mut request = url
+            .as_str()
+            .into_client_request()
+            .map_err(|e| AppError::Connect(e.to_string()))?;
+        if let Some(token) = &self.token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|e| AppError::Connect(format!("token 无效: {}", e)))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        let secure = request.uri().scheme_str() == Some("wss");
+        let connector = if secure {
+            Some(Connector::Rustls(tls::client_config(&self.tls)?))
+        } else {
+            None
+        };
+        let host = request
+            .uri()
+            .host()
+            .map(proxy::unbracket)
+            .unwrap_or_default();
+        let port = request
+            .uri()
+            .port_u16()
+            .unwrap_or(if secure { 443 } else { 80 });
+        let stream = proxy::connect(&self.proxy, secure, host, port).await?;
+        match client_async_tls_with_config(request, stream, None, connector).await {
             Ok((ws_stream, _response)) => {
                 info!("WebSocket 已连接: {}", url);
                 let (mut write, mut read) = ws_stream.split();
 
                 let connected = self.is_connected.clone();
                 let id = self.session_id.clone();
+                let server_audio_params = self.server_audio_params.clone();
+                let binary_version = self.binary_version.clone();
+                *binary_version.write().await = self.protocol_version;
+                self.connected_at = Instant::now();
+                // 上次握手失败后迟到的 hello 不应唤醒本次连接
+                self.hello_received = Arc::new(Notify::new());
                 let hello_received = self.hello_received.clone();
+                // 关闭通知会保留到被等待, 每次连接使用新的通知, 避免上次连接残留的通知
+                self.closed = Arc::new(Notify::new());
                 let closed = self.closed.clone();
-
-                let (frame_sender, frame_recv) =
-                    mpsc::unbounded_channel::<crate::utils::frame::Frame>();
-                let (audio_sender, audio_recv) = mpsc::unbounded_channel::<Vec<u8>>();
+                let rtt = self.rtt.clone();
+                *rtt.write().await = None;
+                // 已发出但未收到 pong 的心跳
+                let ping_sent = SharedAsyncRwLock::new(None::<Instant>.into());
+                let pong_received = Arc::new(Notify::new());
+                let dead = Arc::new(Notify::new());
+                let (ping_sent_, pong_received_, dead_) =
+                    (ping_sent.clone(), pong_received.clone(), dead.clone());
+
+                // 满时丢弃最旧的数据, 消费者停滞时不暂停读取, 心跳 pong 仍能及时处理
+                let (frame_sender, frame_recv) = queue::ring_channel::<crate::utils::frame::Frame>(
+                    queue::CONTROL_CAPACITY,
+                    self.dropped.control_frames.clone(),
+                );
+                let (audio_sender, audio_recv) = queue::ring_channel::<AudioPacket>(
+                    queue::AUDIO_CAPACITY,
+                    self.dropped.downlink_packets.clone(),
+                );
 
                 self.frame_recver.write().await.replace(frame_recv);
                 self.audio_recver.write().await.replace(audio_recv);
 
                 self.input_handle
                     .replace(tauri::async_runtime::spawn(async move {
-                        while let Some(Ok(msg)) = read.next().await {
+                        loop {
+                            let msg = tokio::select! {
+                                msg = read.next() => match msg {
+                                    Some(Ok(msg)) => msg,
+                                    _ => break,
+                                },
+                                _ = dead.notified() => {
+                                    warn!("心跳超时, WebSocket 连接已断开");
+                                    break;
+                                }
+                            };
                             match msg {
                                 Message::Text(text) => {
                                     if let Ok(data) =
                                         serde_json::from_str::<serde_json::Value>(&text)
                                     {
                                         if data["type"] == "hello" && connected.read().await.not() {
-                                            hello_received.notify_one();
                                             *connected.write().await = true;
                                             *id.write().await =
                                                 data["session_id"].as_str().map(|e| e.to_string());
+                                            *server_audio_params.write().await =
+                                                serde_json::from_value(
+                                                    data["audio_params"].clone(),
+                                                )
+                                                .ok();
+                                            if let Some(version) = data["version"]
+                                                .as_u64()
+                                                .and_then(|e| u32::try_from(e).ok())
+                                                .filter(|e| binary::VERSIONS.contains(e))
+                                            {
+                                                *binary_version.write().await = version;
+                                            }
                                             debug!(
                                                 "session_id = {}",
                                                 id.read().await.clone().unwrap_or("".to_string())
                                             );
+                                            // 会话参数就绪后再唤醒 connect
+                                            hello_received.notify_one();
                                         }
 
-                                        let frame = crate::utils::frame::Frame::from(data.clone());
+                                        let is_goodbye = data["type"] == "goodbye";
+                                        let frame = crate::utils::frame::Frame::parse(data);
                                         trace!("控制帧:\n{:#?}", frame);
 
-                                        frame_sender
-                                            .send(frame)
-                                            .map_err(|e| e.to_string())
-                                            .unwrap_or_else(|e| {
-                                                error!("发送控制帧失败: {}", e);
-                                            });
+                                        if frame_sender.send(frame).is_err() {
+                                            debug!("控制帧接收端已关闭, 丢弃控制帧");
+                                        }
+
+                                        if is_goodbye {
+                                            debug!("服务器结束会话");
+                                            closed.notify_one();
+                                        }
                                     }
                                 }
                                 Message::Binary(bytes) => {
-                                    audio_sender.send(bytes.to_vec()).unwrap_or_else(|e| {
-                                        error!("发送音频数据失败: {}", e);
-                                    });
+                                    let version = *binary_version.read().await;
+                                    let frame = match BinaryFrame::decode(version, &bytes) {
+                                        Ok(frame) => frame,
+                                        Err(e) => {
+                                            warn!("二进制帧解析失败: {}", e);
+                                            continue;
+                                        }
+                                    };
+                                    match frame.kind {
+                                        PayloadType::Audio => {
+                                            // 仅版本 2 携带服务器时间戳
+                                            let timestamp =
+                                                (version == 2).then_some(frame.timestamp);
+                                            let packet = AudioPacket::new(frame.payload, timestamp);
+                                            if audio_sender.send(packet).is_err() {
+                                                debug!("音频接收端已关闭, 丢弃音频数据");
+                                            }
+                                        }
+                                        PayloadType::Json => {
+                                            match serde_json::from_slice(&frame.payload) {
+                                                Ok(data) => {
+                                                    let frame =
+                                                        crate::utils::frame::Frame::parse(data);
+                                                    if frame_sender.send(frame).is_err() {
+                                                        debug!("控制帧接收端已关闭, 丢弃控制帧");
+                                                    }
+                                                }
+                                                Err(e) => warn!("二进制 JSON 负载解析失败: {}", e),
+                                            }
+                                        }
+                                        PayloadType::Other(kind) => {
+                                            debug!("忽略二进制负载, 类型: {}", kind);
+                                        }
+                                    }
                                 }
                                 Message::Close(frame) => {
                                     debug!("WebSocket 连接关闭: {:?}", frame);
-                                    closed.notify_waiters();
+                                    break;
+                                }
+                                Message::Pong(_) => {
+                                    if let Some(sent) = ping_sent.write().await.take() {
+                                        trace!("心跳往返时延: {:?}", sent.elapsed());
+                                        *rtt.write().await = Some(sent.elapsed());
+                                        pong_received.notify_one();
+                                    }
                                 }
                                 _ => {
                                     debug!("Received message:\n{:#?}", msg);
                                 }
                             }
                         }
+                        // 读取出错、连接关闭或心跳超时, 通知在无人等待时保留
+                        *connected.write().await = false;
+                        closed.notify_one();
                         debug!("WebSocket 输入处理线程结束");
                     }));
 
                 debug!("ws 输入处理线程启动成功");
 
-                let (tx, mut rx) = mpsc::unbounded_channel();
-                self.msg_sender = Some(tx);
+                let (tx, mut rx) = mpsc::channel(queue::CONTROL_CAPACITY);
+                self.msg_sender = Some(tx.clone());
+                let (audio_tx, audio_rx) = queue::ring_channel::<Vec<u8>>(
+                    queue::AUDIO_CAPACITY,
+                    self.dropped.uplink_packets.clone(),
+                );
+                self.audio_sender = Some(audio_tx);
 
                 self.output_handle
                     .replace(tauri::async_runtime::spawn(async move {
-                        while let Some(msg) = rx.recv().await {
+                        loop {
+                            // 控制消息优先于音频
+                            let msg = tokio::select! {
+                                biased;
+                                msg = rx.recv() => match msg {
+                                    Some(msg) => msg,
+                                    None => break,
+                                },
+                                data = audio_rx.recv() => match data {
+                                    Some(data) => Message::Binary(data.into()),
+                                    None => break,
+                                },
+                            };
                             if write.send(msg).await.is_err() {
                                 break;
                             }
@@ -140,29 +434,31 @@ impl WebsocketProtocol {
                     }));
                 debug!("ws 输出处理线程启动成功");
 
-                let hello_msg = json!(
-                    {
-                        "type":"hello",
-                        "version":1,
-                        "transport":"websocket",
-                        "audio_params":
-                        {
-                            "format":"opus",
-                            "sample_rate":16000,
-                            "channels":1,
-                            "frame_duration":60
-                        }
-                    }
-                );
-                self.send_text(hello_msg.to_string()).await?;
-                debug!("发送hello消息: {}", &hello_msg);
+                if self.ping_interval.is_zero().not() {
+                    let (interval, timeout) = (self.ping_interval, self.pong_timeout);
+                    self.ping_handle
+                        .replace(tauri::async_runtime::spawn(async move {
+                            loop {
+                                tokio::time::sleep(interval).await;
+                                ping_sent_.write().await.replace(Instant::now());
+                                if tx.send(Message::Ping(Default::default())).await.is_err() {
+                                    break;
+                                }
+                                let pong = tokio::time::timeout(timeout, pong_received_.notified());
+                                if pong.await.is_err() {
+                                    warn!("{:?} 内未收到 pong", timeout);
+                                    dead_.notify_one();
+                                    break;
+                                }
+                            }
+                            debug!("WebSocket 心跳线程结束");
+                        }));
+                }
 
-                tokio::time::timeout(
-                    std::time::Duration::from_secs(5),
-                    self.hello_received.notified(),
-                )
-                .await
-                .map_err(|_| "等待服务器hello响应超时".to_string())?;
+                if let Err(e) = self.handshake().await {
+                    self.abort().await;
+                    return Err(e);
+                }
 
                 Ok(self
                     .session_id
@@ -171,11 +467,11 @@ impl WebsocketProtocol {
                     .clone()
                     .unwrap_or("None".to_string()))
             }
-            Err(e) => Err(format!("WebSocket连接失败: {}", e)),
+            Err(e) => Err(AppError::Connect(e.to_string())),
         }
     }
 
-    pub async fn close(&mut self) -> Result<(), String> {
+    async fn close(&mut self) -> Result<(), AppError> {
         if self.is_connected().await.not() {
             warn!("WebSocket 未连接，拒绝重复关闭");
             return Ok(());
@@ -183,12 +479,20 @@ impl WebsocketProtocol {
 
         self.is_connected.write().await.clone_from(&false);
 
+        // 心跳线程持有发送端, 需先结束才能关闭输出处理线程
+        if let Some(t) = self.ping_handle.take() {
+            t.abort();
+            t.await.ok();
+        }
         if let Some(sender) = self.msg_sender.take() {
-            sender
-                .send(Message::Close(None))
-                .map_err(|_| "关闭WebSocket连接失败".to_string())?;
+            // 队列已满时不再等待, 释放发送端后输出处理线程随之结束
+            if let Err(TrySendError::Closed(_)) = sender.try_send(Message::Close(None)) {
+                return Err(AppError::Send("关闭WebSocket连接失败".to_string()));
+            }
             drop(sender);
         }
+        // 在关闭消息入队后释放, 控制消息优先, 输出处理线程先发送关闭消息
+        self.audio_sender.take();
         if let Some(mut recver) = self.frame_recver.write().await.take() {
             recver.close();
             drop(recver);
@@ -200,77 +504,74 @@ impl WebsocketProtocol {
 
         if let Some(t) = self.input_handle.take() {
             t.await
-                .map_err(|_| "WebSocket输入处理线程关闭失败".to_string())?;
+                .map_err(|e| AppError::Internal(format!("WebSocket输入处理线程关闭失败: {}", e)))?;
         };
         if let Some(t) = self.output_handle.take() {
             t.await
-                .map_err(|_| "WebSocket输出处理线程关闭失败".to_string())?;
+                .map_err(|e| AppError::Internal(format!("WebSocket输出处理线程关闭失败: {}", e)))?;
         }
 
         Ok(())
     }
-}
 
-impl WebsocketProtocol {
-    pub fn get_closed_notify(&self) -> Arc<Notify> {
+    fn get_closed_notify(&self) -> Arc<Notify> {
         self.closed.clone()
     }
 
-    pub async fn send_audio(&self, data: Vec<u8>) -> Result<(), String> {
-        if let Some(sender) = &self.msg_sender {
+    async fn send_audio(&self, data: Vec<u8>) -> Result<(), AppError> {
+        if let Some(sender) = &self.audio_sender {
+            let timestamp = self.connected_at.elapsed().as_millis() as u32;
+            let data = BinaryFrame::audio(data, timestamp)
+                .encode(*self.binary_version.read().await)
+                .map_err(|e| AppError::Send(e.to_string()))?;
             sender
-                .send(Message::Binary(data.into()))
-                .map_err(|_| "发送音频数据失败".to_string())
+                .send(data)
+                .map_err(|_| AppError::Send("发送音频数据失败".to_string()))
         } else {
-            Err("WebSocket未连接".to_string())
+            Err(AppError::NotConnected)
         }
     }
 
-    pub async fn send_text(&self, message: String) -> Result<(), String> {
+    async fn send_text(&self, message: String) -> Result<(), AppError> {
         if let Some(sender) = &self.msg_sender {
             sender
                 .send(Message::Text(message.into()))
-                .map_err(|_| "发送文本消息失败".to_string())
+                .await
+                .map_err(|_| AppError::Send("发送文本消息失败".to_string()))
         } else {
-            Err("WebSocket未连接".to_string())
+            Err(AppError::NotConnected)
         }
     }
 
-    pub async fn read_text_frame(&self) -> Option<crate::utils::frame::Frame> {
-        if let Some(recver) = self.frame_recver.write().await.as_mut() {
-            match recver.try_recv() {
-                Ok(frame) => Some(frame),
-                Err(e) => {
-                    trace!("读取控制帧失败: {:?}", e);
-                    None
-                }
-            }
-        } else {
-            None
-        }
+    async fn frames(&self) -> Option<FrameStream> {
+        let recver = self.frame_recver.write().await.take()?;
+        Some(recver.into_stream())
     }
 
-    pub async fn read_audio_data(&self) -> Option<Vec<u8>> {
-        if let Some(recver) = self.audio_recver.write().await.as_mut() {
-            match recver.try_recv() {
-                Ok(data) => Some(data),
-                Err(e) => {
-                    trace!("读取音频数据失败: {:?}", e);
-                    None
-                }
-            }
-        } else {
-            None
-        }
+    async fn audio(&self) -> Option<AudioStream> {
+        let recver = self.audio_recver.write().await.take()?;
+        Some(recver.into_stream())
+    }
+
+    async fn get_server_audio_params(&self) -> Option<AudioParams> {
+        self.server_audio_params.read().await.clone()
     }
 
-    pub async fn get_session_id(&self) -> Option<String> {
+    async fn get_session_id(&self) -> Option<String> {
         self.session_id.read().await.clone()
     }
 
-    pub async fn is_connected(&self) -> bool {
+    async fn get_rtt(&self) -> Option<Duration> {
+        *self.rtt.read().await
+    }
+
+    async fn is_connected(&self) -> bool {
         *self.is_connected.read().await
     }
+
+    fn dropped(&self) -> Dropped {
+        self.dropped.snapshot()
+    }
 }
 
 impl Drop for WebsocketProtocol {
@@ -0,0 +1,179 @@
+//! MQTT + UDP 模拟服务器:
+//! - 收到 `hello` 后在订阅主题上回复带 UDP 参数的 `hello`
+//! - 上行 UDP 数据包原样回传, 客户端以相同密钥解密
+//!
+//! 仅实现 MQTT 3.1.1 中 QoS 0 所需的报文
+
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+
+pub const KEY: &str = "00112233445566778899aabbccddeeff";
+pub const NONCE: &str = "01000000123456780000000000000000";
+
+const CONNECT: u8 = 0x10;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+const DISCONNECT: u8 = 0xE0;
+
+pub struct MockBroker {
+    addr: SocketAddr,
+    script: mpsc::UnboundedSender<Value>,
+    /// 服务器经 MQTT 收到的控制帧
+    received: Arc<Mutex<Vec<Value>>>,
+    /// 服务器经 UDP 收到的数据包
+    packets: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockBroker {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp_port = udp.local_addr().unwrap().port();
+        let (script, mut script_rx) = mpsc::unbounded_channel();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_ = received.clone();
+        let packets = Arc::new(Mutex::new(Vec::new()));
+        let packets_ = packets.clone();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((len, peer)) = udp.recv_from(&mut buf).await {
+                packets_.lock().unwrap().push(buf[..len].to_vec());
+                udp.send_to(&buf[..len], peer).await.ok();
+            }
+        });
+
+        tokio::spawn(async move {
+            let mut n = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                n += 1;
+                let (read, mut write) = stream.into_split();
+                let (packet_tx, mut packet_rx) = mpsc::unbounded_channel();
+                tokio::spawn(read_packets(read, packet_tx));
+
+                let mut topic = String::new();
+                loop {
+                    tokio::select! {
+                        packet = packet_rx.recv() => {
+                            let Some((kind, body)) = packet else { break };
+                            let reply = match kind {
+                                CONNECT => vec![0x20, 2, 0, 0],
+                                SUBSCRIBE => {
+                                    let len = u16::from_be_bytes([body[2], body[3]]) as usize;
+                                    topic = String::from_utf8_lossy(&body[4..4 + len]).to_string();
+                                    vec![0x90, 3, body[0], body[1], 0]
+                                }
+                                PUBLISH => {
+                                    let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                                    let Ok(data) = serde_json::from_slice::<Value>(&body[2 + len..])
+                                    else {
+                                        continue;
+                                    };
+                                    received_.lock().unwrap().push(data.clone());
+                                    if data["type"] != "hello" {
+                                        continue;
+                                    }
+                                    let hello = json!({
+                                        "type": "hello",
+                                        "transport": "udp",
+                                        "session_id": format!("mock-session-{}", n),
+                                        "audio_params": data["audio_params"],
+                                        "udp": {
+                                            "server": "127.0.0.1",
+                                            "port": udp_port,
+                                            "key": KEY,
+                                            "nonce": NONCE,
+                                        },
+                                    });
+                                    publish(&topic, &hello)
+                                }
+                                PINGREQ => vec![0xD0, 0],
+                                DISCONNECT => break,
+                                _ => continue,
+                            };
+                            if write.write_all(&reply).await.is_err() {
+                                break;
+                            }
+                        }
+                        frame = script_rx.recv() => {
+                            let Some(frame) = frame else { break };
+                            if write.write_all(&publish(&topic, &frame)).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Self {
+            addr,
+            script,
+            received,
+            packets,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// 在订阅主题上下发任意控制帧
+    pub fn send_frame(&self, frame: Value) {
+        self.script.send(frame).unwrap();
+    }
+
+    pub fn received(&self) -> Vec<Value> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn packets(&self) -> Vec<Vec<u8>> {
+        self.packets.lock().unwrap().clone()
+    }
+}
+
+/// 读取报文, 发送 (类型, 可变头部与负载)
+async fn read_packets(mut read: OwnedReadHalf, tx: mpsc::UnboundedSender<(u8, Vec<u8>)>) {
+    while let Ok(kind) = read.read_u8().await {
+        let mut len = 0usize;
+        for shift in (0..28).step_by(7) {
+            let Ok(byte) = read.read_u8().await else {
+                return;
+            };
+            len |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0u8; len];
+        if read.read_exact(&mut body).await.is_err() || tx.send((kind, body)).is_err() {
+            break;
+        }
+    }
+}
+
+fn publish(topic: &str, frame: &Value) -> Vec<u8> {
+    let payload = frame.to_string();
+    let mut len = 2 + topic.len() + payload.len();
+    let mut packet = vec![PUBLISH];
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+    packet.extend_from_slice(topic.as_bytes());
+    packet.extend_from_slice(payload.as_bytes());
+    packet
+}
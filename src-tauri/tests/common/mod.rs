@@ -0,0 +1,268 @@
+#![allow(dead_code)]
+//! 集成测试用的本地模拟服务器, 行为与小智服务器协议保持一致:
+//! - 收到 `hello` 后回复带 `session_id` 的 `hello`
+//! - 上行的 Opus 数据原样作为下行数据回传
+//! - 可通过 [`MockServer`] 脚本化下发 `tts`/`stt`/`llm` 控制帧或主动关闭连接
+//!
+//! MQTT + UDP 传输的模拟服务器见 [`broker`], 代理模拟服务器见 [`proxy`]
+
+pub mod broker;
+pub mod proxy;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone)]
+pub struct MockOptions {
+    /// 是否回复客户端的 hello
+    pub reply_hello: bool,
+    /// 忽略每个连接的前几次 hello, 用于测试重发
+    pub ignore_hellos: usize,
+    /// 是否回传上行音频
+    pub echo_audio: bool,
+    /// 回复 hello 后停止读取, 模拟半开连接, 心跳得不到响应
+    pub stall: bool,
+    /// 以 `wss://` 提供服务
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl Default for MockOptions {
+    fn default() -> Self {
+        Self {
+            reply_hello: true,
+            ignore_hellos: 0,
+            echo_audio: true,
+            stall: false,
+            tls: None,
+        }
+    }
+}
+
+enum Script {
+    Text(String),
+    Close,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    script: mpsc::UnboundedSender<Script>,
+    /// 服务器收到的文本消息
+    received: Arc<Mutex<Vec<Value>>>,
+    /// 最近一次握手请求的 Authorization 头
+    authorization: Arc<Mutex<Option<String>>>,
+    /// 已结束的连接数
+    disconnected: Arc<AtomicUsize>,
+    tls: bool,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        Self::with_options(MockOptions::default()).await
+    }
+
+    pub async fn with_options(options: MockOptions) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tls = options.tls.is_some();
+        let (script, mut script_rx) = mpsc::unbounded_channel();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_ = received.clone();
+        let authorization = Arc::new(Mutex::new(None));
+        let authorization_ = authorization.clone();
+        let acceptor = options.tls.clone().map(TlsAcceptor::from);
+        let disconnected = Arc::new(AtomicUsize::new(0));
+        let disconnected_ = disconnected.clone();
+
+        tokio::spawn(async move {
+            let mut n = 0;
+            // 依次处理连接, 脚本只作用于当前连接
+            while let Ok((stream, _)) = listener.accept().await {
+                n += 1;
+                let session_id = format!("mock-session-{}", n);
+                let handle = Handle {
+                    options: &options,
+                    script: &mut script_rx,
+                    received: &received_,
+                    authorization: &authorization_,
+                };
+                match &acceptor {
+                    Some(acceptor) => {
+                        // 客户端证书校验失败等情况下握手失败, 继续等待下一个连接
+                        if let Ok(stream) = acceptor.accept(stream).await {
+                            handle.accept(stream, session_id).await;
+                        }
+                    }
+                    None => handle.accept(stream, session_id).await,
+                }
+                disconnected_.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        Self {
+            addr,
+            script,
+            received,
+            authorization,
+            disconnected,
+            tls,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        if self.tls {
+            // 测试证书签发给 localhost
+            format!("wss://localhost:{}", self.addr.port())
+        } else {
+            format!("ws://{}", self.addr)
+        }
+    }
+
+    /// 下发任意控制帧
+    pub fn send_frame(&self, frame: Value) {
+        self.script.send(Script::Text(frame.to_string())).unwrap();
+    }
+
+    pub fn tts(&self, state: &str, text: Option<&str>) {
+        self.send_frame(json!({"type": "tts", "state": state, "text": text}));
+    }
+
+    pub fn stt(&self, text: &str) {
+        self.send_frame(json!({"type": "stt", "text": text}));
+    }
+
+    pub fn llm(&self, emotion: &str, text: &str) {
+        self.send_frame(json!({"type": "llm", "emotion": emotion, "text": text}));
+    }
+
+    /// 服务器主动结束会话
+    pub fn goodbye(&self) {
+        self.send_frame(json!({"type": "goodbye"}));
+    }
+
+    /// 服务器主动关闭连接
+    pub fn close(&self) {
+        self.script.send(Script::Close).unwrap();
+    }
+
+    pub fn authorization(&self) -> Option<String> {
+        self.authorization.lock().unwrap().clone()
+    }
+
+    pub fn received(&self) -> Vec<Value> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn disconnected(&self) -> usize {
+        self.disconnected.load(Ordering::SeqCst)
+    }
+}
+
+/// 单个连接的处理上下文
+struct Handle<'a> {
+    options: &'a MockOptions,
+    script: &'a mut mpsc::UnboundedReceiver<Script>,
+    received: &'a Arc<Mutex<Vec<Value>>>,
+    authorization: &'a Arc<Mutex<Option<String>>>,
+}
+
+impl Handle<'_> {
+    async fn accept<S>(self, stream: S, session_id: String)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // 回调签名由 tungstenite 决定
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, response| {
+            *self.authorization.lock().unwrap() = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|e| e.to_str().ok())
+                .map(|e| e.to_string());
+            Ok(response)
+        };
+        let Ok(ws) = tokio_tungstenite::accept_hdr_async(stream, callback).await else {
+            return;
+        };
+        serve(ws, session_id, self.options, self.script, self.received).await;
+    }
+}
+
+async fn serve<S>(
+    ws: tokio_tungstenite::WebSocketStream<S>,
+    session_id: String,
+    options: &MockOptions,
+    script: &mut mpsc::UnboundedReceiver<Script>,
+    received: &Arc<Mutex<Vec<Value>>>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut write, mut read) = ws.split();
+    let mut hellos = 0;
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                let Some(Ok(msg)) = msg else { break };
+                match msg {
+                    Message::Text(text) => {
+                        let Ok(data) = serde_json::from_str::<Value>(&text) else {
+                            continue;
+                        };
+                        received.lock().unwrap().push(data.clone());
+                        if data["type"] == "hello" {
+                            hellos += 1;
+                        }
+                        if data["type"] == "hello"
+                            && options.reply_hello
+                            && hellos > options.ignore_hellos
+                        {
+                            let hello = json!({
+                                "type": "hello",
+                                "transport": "websocket",
+                                "session_id": session_id,
+                                "version": data["version"],
+                                "audio_params": data["audio_params"],
+                            });
+                            if write.send(Message::Text(hello.to_string().into())).await.is_err() {
+                                break;
+                            }
+                            if options.stall {
+                                std::future::pending::<()>().await;
+                            }
+                        }
+                    }
+                    Message::Binary(bytes) if options.echo_audio => {
+                        let echo = write.send(Message::Binary(bytes)).await;
+                        if echo.is_err() {
+                            break;
+                        }
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            cmd = script.recv() => {
+                match cmd {
+                    Some(Script::Text(text)) => {
+                        if write.send(Message::Text(text.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Script::Close) | None => {
+                        write.send(Message::Close(None)).await.ok();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! 代理模拟服务器, 支持 HTTP CONNECT 与 SOCKS5, 可要求用户名密码
+//!
+//! 建立隧道后双向转发数据, 记录每次隧道的目标地址
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Http,
+    Socks5,
+}
+
+pub struct MockProxy {
+    addr: SocketAddr,
+    kind: Kind,
+    /// 已建立隧道的目标地址
+    targets: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockProxy {
+    /// `auth` 为需要的用户名与密码
+    pub async fn start(kind: Kind, auth: Option<(&str, &str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let targets_ = targets.clone();
+        let auth = auth.map(|(user, pass)| (user.to_string(), pass.to_string()));
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (auth, targets) = (auth.clone(), targets_.clone());
+                tokio::spawn(async move {
+                    let tunnel = match kind {
+                        Kind::Http => http(stream, auth).await,
+                        Kind::Socks5 => socks5(stream, auth).await,
+                    };
+                    if let Some((mut client, mut upstream, target)) = tunnel {
+                        targets.lock().unwrap().push(target);
+                        tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                            .await
+                            .ok();
+                    }
+                });
+            }
+        });
+
+        Self {
+            addr,
+            kind,
+            targets,
+        }
+    }
+
+    /// 代理地址, `userinfo` 非空时带上用户名密码
+    pub fn url(&self, userinfo: &str) -> String {
+        let scheme = match self.kind {
+            Kind::Http => "http",
+            Kind::Socks5 => "socks5",
+        };
+        match userinfo {
+            "" => format!("{}://{}", scheme, self.addr),
+            _ => format!("{}://{}@{}", scheme, userinfo, self.addr),
+        }
+    }
+
+    pub fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+type Tunnel = (TcpStream, TcpStream, String);
+
+async fn http(mut stream: TcpStream, auth: Option<(String, String)>) -> Option<Tunnel> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.ok()?);
+    }
+    let head = String::from_utf8(head).ok()?;
+    let target = head.split_whitespace().nth(1)?.to_string();
+    if let Some((user, pass)) = auth {
+        let expected = format!(
+            "proxy-authorization: basic {}",
+            STANDARD.encode(format!("{}:{}", user, pass))
+        );
+        if !head.lines().any(|e| e.eq_ignore_ascii_case(&expected)) {
+            stream
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await
+                .ok();
+            return None;
+        }
+    }
+    let upstream = TcpStream::connect(&target).await.ok()?;
+    stream
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await
+        .ok()?;
+    Some((stream, upstream, target))
+}
+
+async fn socks5(mut stream: TcpStream, auth: Option<(String, String)>) -> Option<Tunnel> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await.ok()?;
+    let mut methods = vec![0u8; head[1] as usize];
+    stream.read_exact(&mut methods).await.ok()?;
+
+    match auth {
+        Some((user, pass)) if methods.contains(&0x02) => {
+            stream.write_all(&[0x05, 0x02]).await.ok()?;
+            let read = async |stream: &mut TcpStream| {
+                let len = stream.read_u8().await.ok()? as usize;
+                let mut buf = vec![0u8; len];
+                stream.read_exact(&mut buf).await.ok()?;
+                String::from_utf8(buf).ok()
+            };
+            stream.read_u8().await.ok()?;
+            let (u, p) = (read(&mut stream).await?, read(&mut stream).await?);
+            if (u, p) != (user, pass) {
+                stream.write_all(&[0x01, 0x01]).await.ok();
+                return None;
+            }
+            stream.write_all(&[0x01, 0x00]).await.ok()?;
+        }
+        Some(_) => {
+            stream.write_all(&[0x05, 0xFF]).await.ok();
+            return None;
+        }
+        None => stream.write_all(&[0x05, 0x00]).await.ok()?,
+    }
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await.ok()?;
+    let host = match request[3] {
+        0x01 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await.ok()?;
+            IpAddr::from(Ipv4Addr::from(ip)).to_string()
+        }
+        0x04 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip).await.ok()?;
+            IpAddr::from(Ipv6Addr::from(ip)).to_string()
+        }
+        _ => {
+            let len = stream.read_u8().await.ok()? as usize;
+            let mut name = vec![0u8; len];
+            stream.read_exact(&mut name).await.ok()?;
+            String::from_utf8(name).ok()?
+        }
+    };
+    let port = stream.read_u16().await.ok()?;
+    let target = format!("{}:{}", host, port);
+    let upstream = TcpStream::connect(&target).await.ok()?;
+    stream
+        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await
+        .ok()?;
+    Some((stream, upstream, target))
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use app_lib::utils::config::MqttCfg;
+use app_lib::utils::frame::{Frame, client::ClientMessage};
+use app_lib::utils::mqtt::{MqttUdpProtocol, UdpCrypto};
+use app_lib::utils::transport::{FrameStream, Transport};
+use common::broker::{KEY, MockBroker, NONCE};
+use futures_util::StreamExt;
+use serde_json::json;
+use std::time::Duration;
+
+fn cfg(broker: &MockBroker) -> MqttCfg {
+    MqttCfg {
+        endpoint: broker.endpoint(),
+        client_id: "t-app-test".to_string(),
+        publish_topic: "device-server".to_string(),
+        subscribe_topic: "devices/t-app-test".to_string(),
+        ..Default::default()
+    }
+}
+
+async fn next_frame(frames: &mut FrameStream) -> Frame {
+    tokio::time::timeout(Duration::from_secs(2), frames.next())
+        .await
+        .expect("等待控制帧超时")
+        .expect("连接已关闭")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connect() {
+    let broker = MockBroker::start().await;
+    let mut mqtt = MqttUdpProtocol::new(cfg(&broker));
+
+    assert_eq!(mqtt.connect().await.unwrap(), "mock-session-1");
+    assert!(mqtt.is_connected().await);
+    let hello = serde_json::from_value::<ClientMessage>(broker.received()[0].clone()).unwrap();
+    assert!(matches!(hello, ClientMessage::Hello { transport, .. } if transport == "udp"));
+    let mut frames = mqtt.frames().await.unwrap();
+    assert!(
+        matches!(next_frame(&mut frames).await, Frame::Unknown { kind, .. } if kind == "hello")
+    );
+
+    mqtt.send_message(ClientMessage::goodbye(Some("mock-session-1".to_string())))
+        .await
+        .unwrap();
+    broker.send_frame(json!({"type": "stt", "text": "你好"}));
+    assert!(matches!(next_frame(&mut frames).await, Frame::SttFrame(_)));
+    // 上行发布与下行帧无先后保证, 等待代理收到
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while broker.received().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("等待上行消息超时");
+    assert_eq!(broker.received()[1]["type"], "goodbye");
+
+    mqtt.close().await.unwrap();
+    assert!(!mqtt.is_connected().await);
+    assert_eq!(
+        mqtt.send_audio(vec![1]).await.unwrap_err().code(),
+        "NOT_CONNECTED"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn udp_audio() {
+    let broker = MockBroker::start().await;
+    let mut mqtt = MqttUdpProtocol::new(cfg(&broker));
+    mqtt.connect().await.unwrap();
+
+    let mut audio = mqtt.audio().await.unwrap();
+    mqtt.send_audio(vec![1, 2, 3]).await.unwrap();
+    let packet = tokio::time::timeout(Duration::from_secs(2), audio.next())
+        .await
+        .expect("等待音频数据超时")
+        .expect("连接已关闭");
+    assert_eq!(packet.payload, [1, 2, 3]);
+    assert!(packet.timestamp.is_some());
+
+    // 上行数据已加密
+    let packet = broker.packets()[0].clone();
+    assert_ne!(packet[16..], [1, 2, 3]);
+    let crypto = UdpCrypto::new(KEY, NONCE).unwrap();
+    assert_eq!(
+        crypto.decrypt(&packet).unwrap().map(|e| e.payload),
+        Some(vec![1, 2, 3])
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn goodbye() {
+    let broker = MockBroker::start().await;
+    let mut mqtt = MqttUdpProtocol::new(cfg(&broker));
+    mqtt.connect().await.unwrap();
+
+    let closed = mqtt.get_closed_notify();
+    let notified = closed.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+
+    broker.send_frame(json!({"type": "goodbye"}));
+    tokio::time::timeout(Duration::from_secs(2), notified)
+        .await
+        .expect("未收到会话结束通知");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connect_failed() {
+    let broker = MockBroker::start().await;
+    let mut cfg = cfg(&broker);
+    cfg.endpoint = "127.0.0.1:1".to_string();
+    let mut mqtt = MqttUdpProtocol::new(cfg);
+    assert_eq!(mqtt.connect().await.unwrap_err().code(), "CONNECT");
+}
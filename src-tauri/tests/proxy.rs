@@ -0,0 +1,76 @@
+mod common;
+
+use app_lib::error::AppError;
+use app_lib::utils::config::ProxyCfg;
+use app_lib::utils::transport::Transport;
+use app_lib::utils::ws::WebsocketProtocol;
+use common::MockServer;
+use common::proxy::{Kind, MockProxy};
+
+/// 仅使用配置中的代理, 不受运行环境的代理变量影响
+fn proxy(url: String) -> ProxyCfg {
+    ProxyCfg {
+        url: Some(url),
+        use_env: false,
+        no_proxy: vec![],
+    }
+}
+
+async fn tunnel(kind: Kind) {
+    let server = MockServer::start().await;
+    let target = server.url().trim_start_matches("ws://").to_string();
+    let proxy_server = MockProxy::start(kind, Some(("user", "p@ss"))).await;
+
+    let mut ws =
+        WebsocketProtocol::new(server.url()).with_proxy(proxy(proxy_server.url("user:wrong")));
+    assert_eq!(ws.connect().await.unwrap_err().code(), "CONNECT");
+    assert_eq!(proxy_server.targets(), [] as [&str; 0]);
+
+    let mut ws =
+        WebsocketProtocol::new(server.url()).with_proxy(proxy(proxy_server.url("user:p%40ss")));
+    assert_eq!(ws.connect().await.unwrap(), "mock-session-1");
+    assert_eq!(proxy_server.targets(), [target]);
+    ws.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn http_connect() {
+    tunnel(Kind::Http).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn socks5() {
+    tunnel(Kind::Socks5).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn socks5_too_long() {
+    let server = MockServer::start().await;
+    let proxy_server = MockProxy::start(Kind::Socks5, Some(("user", "p@ss"))).await;
+
+    // 密码长度无法用一个字节表示, 不发送截断后的认证请求
+    let userinfo = format!("user:{}", "x".repeat(256));
+    let mut ws =
+        WebsocketProtocol::new(server.url()).with_proxy(proxy(proxy_server.url(&userinfo)));
+    let err = ws.connect().await.unwrap_err();
+    assert!(
+        matches!(&err, AppError::Connect(e) if e.contains("过长")),
+        "{}",
+        err
+    );
+    assert_eq!(proxy_server.targets(), [] as [&str; 0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_proxy() {
+    let server = MockServer::start().await;
+    let proxy_server = MockProxy::start(Kind::Http, None).await;
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_proxy(ProxyCfg {
+        no_proxy: vec!["127.0.0.1".to_string()],
+        ..proxy(proxy_server.url(""))
+    });
+    ws.connect().await.unwrap();
+    assert_eq!(proxy_server.targets(), [] as [&str; 0]);
+    ws.close().await.unwrap();
+}
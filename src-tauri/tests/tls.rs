@@ -0,0 +1,205 @@
+mod common;
+
+use app_lib::utils::config::TlsCfg;
+use app_lib::utils::tls;
+use app_lib::utils::transport::Transport;
+use app_lib::utils::ws::WebsocketProtocol;
+use common::{MockOptions, MockServer};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// 测试用 CA 及其签发的服务器与客户端证书, PEM 文件写入临时目录
+struct Pki {
+    dir: PathBuf,
+    ca: Certificate,
+    ca_key: KeyPair,
+    server: Certificate,
+    server_key: KeyPair,
+}
+
+impl Pki {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("t-app-tls-test-{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "T-App Test CA");
+        let ca = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        let (server, server_key) = Self::issue(&ca, &ca_key);
+
+        let client_key = KeyPair::generate().unwrap();
+        let client = CertificateParams::new(Vec::<String>::new())
+            .unwrap()
+            .signed_by(&client_key, &ca, &ca_key)
+            .unwrap();
+        std::fs::write(dir.join("client.pem"), client.pem()).unwrap();
+        std::fs::write(dir.join("client.key"), client_key.serialize_pem()).unwrap();
+
+        Self {
+            dir,
+            ca,
+            ca_key,
+            server,
+            server_key,
+        }
+    }
+
+    /// CA 签发的 localhost 服务器证书
+    fn issue(ca: &Certificate, ca_key: &KeyPair) -> (Certificate, KeyPair) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, ca, ca_key)
+            .unwrap();
+        (cert, key)
+    }
+
+    /// 服务器 TLS 配置, `client_auth` 为真时要求 CA 签发的客户端证书
+    fn server_config(&self, client_auth: bool) -> Arc<ServerConfig> {
+        let chain = vec![self.server.der().clone(), self.ca.der().clone()];
+        self.server_config_with(chain, &self.server_key, client_auth)
+    }
+
+    fn server_config_with(
+        &self,
+        chain: Vec<CertificateDer<'static>>,
+        key: &KeyPair,
+        client_auth: bool,
+    ) -> Arc<ServerConfig> {
+        let provider = Arc::new(default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = if client_auth {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca.der().clone()).unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let key = PrivatePkcs8KeyDer::from(key.serialize_der());
+        Arc::new(builder.with_single_cert(chain, key.into()).unwrap())
+    }
+
+    async fn server(&self, client_auth: bool) -> MockServer {
+        MockServer::with_options(MockOptions {
+            tls: Some(self.server_config(client_auth)),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// 信任测试 CA 的客户端配置
+    fn tls(&self) -> TlsCfg {
+        TlsCfg {
+            ca_file: Some(self.dir.join("ca.pem")),
+            ..Default::default()
+        }
+    }
+}
+
+fn pin(cert: &CertificateDer) -> String {
+    tls::spki_pin(cert).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn custom_ca() {
+    let pki = Pki::new("custom-ca");
+    let server = pki.server(false).await;
+
+    // 仅信任内置根证书时拒绝私有 CA 签发的证书
+    let mut ws = WebsocketProtocol::new(server.url());
+    let err = ws.connect().await.unwrap_err();
+    assert_eq!(err.code(), "CONNECT");
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_tls(pki.tls());
+    assert_eq!(ws.connect().await.unwrap(), "mock-session-2");
+    ws.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pinning() {
+    let pki = Pki::new("pinning");
+    let server = pki.server(false).await;
+
+    for (pins, ok) in [
+        (vec![pin(pki.server.der())], true),
+        // CA 位于校验所用的证书路径中, 固定 CA 公钥同样有效
+        (
+            vec![
+                "sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string(),
+                pin(pki.ca.der()),
+            ],
+            true,
+        ),
+        (
+            vec!["sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string()],
+            false,
+        ),
+    ] {
+        let mut ws = WebsocketProtocol::new(server.url()).with_tls(TlsCfg {
+            pins: pins.clone(),
+            ..pki.tls()
+        });
+        assert_eq!(ws.connect().await.is_ok(), ok, "{:?}", pins);
+        ws.close().await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pinning_extra_cert() {
+    let pki = Pki::new("pinning-extra-cert");
+    // 同一 CA 误签发的证书, 附带固定的服务器证书作为多余的中间证书
+    let (rogue, rogue_key) = Pki::issue(&pki.ca, &pki.ca_key);
+    let chain = vec![
+        rogue.der().clone(),
+        pki.server.der().clone(),
+        pki.ca.der().clone(),
+    ];
+    let server = MockServer::with_options(MockOptions {
+        tls: Some(pki.server_config_with(chain, &rogue_key, false)),
+        ..Default::default()
+    })
+    .await;
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_tls(TlsCfg {
+        pins: vec![pin(pki.server.der())],
+        ..pki.tls()
+    });
+    assert!(ws.connect().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutual_tls() {
+    let pki = Pki::new("mutual-tls");
+    let server = pki.server(true).await;
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_tls(pki.tls());
+    assert!(ws.connect().await.is_err());
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_tls(TlsCfg {
+        client_cert: Some(pki.dir.join("client.pem")),
+        client_key: Some(pki.dir.join("client.key")),
+        ..pki.tls()
+    });
+    assert!(ws.connect().await.unwrap().starts_with("mock-session-"));
+    assert!(ws.is_connected().await);
+    ws.close().await.unwrap();
+}
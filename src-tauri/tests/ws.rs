@@ -0,0 +1,372 @@
+mod common;
+
+use app_lib::error::AppError;
+use app_lib::utils::frame::{
+    Frame,
+    client::{AudioParams, ClientMessage},
+    tts::TtsState,
+};
+use app_lib::utils::queue::{AUDIO_CAPACITY, CONTROL_CAPACITY};
+use app_lib::utils::transport::{AudioPacket, AudioStream, FrameStream, Transport};
+use app_lib::utils::ws::WebsocketProtocol;
+use common::{MockOptions, MockServer};
+use futures_util::StreamExt;
+use std::ops::Not;
+use std::time::Duration;
+
+async fn next_frame(frames: &mut FrameStream) -> Frame {
+    tokio::time::timeout(Duration::from_secs(2), frames.next())
+        .await
+        .expect("等待控制帧超时")
+        .expect("连接已关闭")
+}
+
+async fn next_audio(audio: &mut AudioStream) -> AudioPacket {
+    tokio::time::timeout(Duration::from_secs(2), audio.next())
+        .await
+        .expect("等待音频数据超时")
+        .expect("连接已关闭")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connect() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+
+    let id = ws.connect().await.unwrap();
+    assert_eq!(id, "mock-session-1");
+    assert!(ws.is_connected().await);
+    assert_eq!(ws.get_session_id().await.as_deref(), Some("mock-session-1"));
+
+    // 重复连接返回同一会话
+    assert_eq!(ws.connect().await.unwrap(), "mock-session-1");
+    let hello = serde_json::from_value::<ClientMessage>(server.received()[0].clone()).unwrap();
+    assert!(matches!(hello, ClientMessage::Hello { .. }));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_message() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    let id = ws.connect().await.unwrap();
+
+    let msg = ClientMessage::listen_detect(Some(id), "你好".to_string());
+    ws.send_message(msg.clone()).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while server.received().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let received = serde_json::from_value::<ClientMessage>(server.received()[1].clone()).unwrap();
+    assert_eq!(received, msg);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn audio_echo() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    ws.connect().await.unwrap();
+    let mut frames = ws.frames().await.unwrap();
+    let mut audio = ws.audio().await.unwrap();
+    assert!(ws.frames().await.is_none());
+
+    // hello 本身会作为控制帧投递
+    next_frame(&mut frames).await;
+
+    ws.send_audio(vec![1, 2, 3]).await.unwrap();
+    assert_eq!(next_audio(&mut audio).await.payload, [1, 2, 3]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn audio_overflow() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    ws.connect().await.unwrap();
+
+    // 不读取下行音频, 超出容量的最旧数据包被丢弃
+    let extra = 8;
+    for i in 0..(AUDIO_CAPACITY + extra) as u16 {
+        ws.send_audio(i.to_be_bytes().to_vec()).await.unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let dropped = ws.dropped();
+            if dropped.uplink_packets + dropped.downlink_packets == extra as u64 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("等待音频丢弃超时");
+    let mut audio = ws.audio().await.unwrap();
+    assert_eq!(
+        next_audio(&mut audio).await.payload,
+        (extra as u16).to_be_bytes()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn session_start_stop() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    ws.connect().await.unwrap();
+    let mut frames = ws.frames().await.unwrap();
+    next_frame(&mut frames).await;
+
+    server.stt("你好");
+    server.llm("happy", "😀");
+    server.tts("start", None);
+    server.tts("sentence_start", Some("你好呀"));
+    server.tts("sentence_end", Some("你好呀"));
+    server.tts("stop", None);
+
+    let mut states = Vec::new();
+    while states.len() < 4 {
+        if let Frame::TtsFrame(frame) = next_frame(&mut frames).await {
+            if let TtsState::SentenceStart = frame.state {
+                assert_eq!(frame.text.as_deref(), Some("你好呀"));
+            }
+            states.push(frame.state.to_string());
+        }
+    }
+    assert_eq!(states, ["start", "sentence_start", "sentence_end", "stop"]);
+
+    ws.close().await.unwrap();
+    assert!(!ws.is_connected().await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hello_timeout() {
+    let server = MockServer::with_options(MockOptions {
+        reply_hello: false,
+        ..Default::default()
+    })
+    .await;
+    let mut ws = WebsocketProtocol::new(server.url()).with_handshake(Duration::from_millis(100), 2);
+
+    let err = ws.connect().await.unwrap_err();
+    assert!(matches!(err, AppError::HandshakeTimeout), "{}", err);
+    assert!(!ws.is_connected().await);
+    assert_eq!(server.received().len(), 3);
+
+    // 握手失败后连接已关闭
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while server.disconnected() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("连接未关闭");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hello_retry() {
+    let server = MockServer::with_options(MockOptions {
+        ignore_hellos: 1,
+        ..Default::default()
+    })
+    .await;
+    let mut ws = WebsocketProtocol::new(server.url()).with_handshake(Duration::from_millis(100), 1);
+
+    assert_eq!(ws.connect().await.unwrap(), "mock-session-1");
+    assert_eq!(server.received().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_close() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    ws.connect().await.unwrap();
+
+    let closed = ws.get_closed_notify();
+    let notified = closed.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+
+    server.close();
+    tokio::time::timeout(Duration::from_secs(2), notified)
+        .await
+        .expect("未收到连接关闭通知");
+    assert!(ws.is_connected().await.not());
+
+    // 连接断开后才开始等待, 仍能收到通知
+    ws.connect().await.unwrap();
+    let closed = ws.get_closed_notify();
+    server.close();
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while ws.is_connected().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("连接断开后仍为已连接状态");
+    tokio::time::timeout(Duration::from_secs(2), closed.notified())
+        .await
+        .expect("未收到连接关闭通知");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connect_refused() {
+    // 先占用端口再释放, 确保无人监听
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let mut ws = WebsocketProtocol::new(url);
+    assert!(matches!(ws.connect().await, Err(AppError::Connect(_))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_goodbye() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url());
+    ws.connect().await.unwrap();
+    let mut frames = ws.frames().await.unwrap();
+    next_frame(&mut frames).await;
+
+    let closed = ws.get_closed_notify();
+    let notified = closed.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+
+    server.goodbye();
+    tokio::time::timeout(Duration::from_secs(2), notified)
+        .await
+        .expect("未收到会话结束通知");
+    assert!(matches!(
+        next_frame(&mut frames).await,
+        Frame::GoodbyeFrame(_)
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn token() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url()).with_token(Some("secret".to_string()));
+    ws.connect().await.unwrap();
+    assert_eq!(server.authorization().as_deref(), Some("Bearer secret"));
+    ws.close().await.unwrap();
+
+    let mut ws = WebsocketProtocol::new(server.url()).with_token(Some(String::new()));
+    ws.connect().await.unwrap();
+    assert_eq!(server.authorization(), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn audio_params() {
+    let server = MockServer::start().await;
+    let params = AudioParams {
+        sample_rate: 24000,
+        channels: 2,
+        frame_duration: 20,
+        ..Default::default()
+    };
+    let mut ws = WebsocketProtocol::new(server.url()).with_audio_params(params.clone());
+    ws.connect().await.unwrap();
+
+    let ClientMessage::Hello { audio_params, .. } =
+        serde_json::from_value(server.received()[0].clone()).unwrap()
+    else {
+        panic!("首条消息不是 hello");
+    };
+    assert_eq!(audio_params, params);
+    // 模拟服务器原样回复客户端的音频参数
+    assert_eq!(ws.get_server_audio_params().await, Some(params));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn binary_protocol() {
+    for version in [1, 2, 3] {
+        let server = MockServer::start().await;
+        let mut ws = WebsocketProtocol::new(server.url()).with_protocol_version(version);
+        ws.connect().await.unwrap();
+        assert_eq!(ws.get_binary_version().await, version);
+        let mut audio = ws.audio().await.unwrap();
+
+        // 模拟服务器原样回传带头部的数据包
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        ws.send_audio(vec![1, 2, 3]).await.unwrap();
+        let packet = next_audio(&mut audio).await;
+        assert_eq!(packet.payload, [1, 2, 3], "{}", version);
+        assert_eq!(
+            packet.timestamp.is_some_and(|e| e > 0),
+            version == 2,
+            "{}",
+            version
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keepalive() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url())
+        .with_keepalive(Duration::from_millis(50), Duration::from_millis(500));
+    ws.connect().await.unwrap();
+    assert_eq!(ws.get_rtt().await, None);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(ws.get_rtt().await.is_some());
+    ws.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn control_overflow() {
+    let server = MockServer::start().await;
+    let mut ws = WebsocketProtocol::new(server.url())
+        .with_keepalive(Duration::from_millis(50), Duration::from_millis(300));
+    ws.connect().await.unwrap();
+
+    // 不读取控制帧, 超出容量的最旧控制帧被丢弃, 心跳仍正常处理
+    let extra = 4;
+    for i in 0..CONTROL_CAPACITY + extra {
+        server.stt(&i.to_string());
+    }
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while ws.dropped().control_frames < extra as u64 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("等待控制帧丢弃超时");
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(ws.is_connected().await);
+    assert!(ws.get_rtt().await.is_some());
+
+    let mut frames = ws.frames().await.unwrap();
+    let Frame::SttFrame(frame) = next_frame(&mut frames).await else {
+        panic!("应为 stt 帧");
+    };
+    assert_eq!(frame.text, extra.to_string());
+    ws.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pong_timeout() {
+    let server = MockServer::with_options(MockOptions {
+        stall: true,
+        ..Default::default()
+    })
+    .await;
+    let mut ws = WebsocketProtocol::new(server.url())
+        .with_keepalive(Duration::from_millis(50), Duration::from_millis(100));
+    ws.connect().await.unwrap();
+
+    let closed = ws.get_closed_notify();
+    let notified = closed.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+    tokio::time::timeout(Duration::from_secs(2), notified)
+        .await
+        .expect("未检测到连接断开");
+
+    // 输入处理线程已结束, 关闭不会阻塞
+    tokio::time::timeout(Duration::from_secs(2), ws.close())
+        .await
+        .expect("关闭连接超时")
+        .unwrap();
+}